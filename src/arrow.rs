@@ -0,0 +1,191 @@
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_array::builder::{StringBuilder, UInt32Builder, UInt64Builder};
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, UInt32Array};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use chess_pgn_parser::{Game, GameTermination};
+
+use pgn::GamePosition;
+use {OpeningKey, OpeningResult, Result};
+
+// The number of games written at a time
+const BATCH_SIZE: usize = 8192;
+
+/// Writes a table of games as an Arrow IPC file, which can be read by pandas with
+/// `read_feather` and by polars with `read_ipc`.
+///
+/// The games are written in batches as they are added so they don't all have to be held in
+/// memory.
+pub struct GameTableWriter<W: Write> {
+    writer: FileWriter<W>,
+    schema: SchemaRef,
+    file: StringBuilder,
+    game_index: UInt64Builder,
+    line: UInt64Builder,
+    white: StringBuilder,
+    black: StringBuilder,
+    result: StringBuilder,
+    opening: StringBuilder,
+    ply_count: UInt32Builder,
+    row_count: usize,
+}
+
+impl<W: Write> GameTableWriter<W> {
+    pub fn new(file: W) -> Result<GameTableWriter<W>> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("file", DataType::Utf8, false),
+            Field::new("game_index", DataType::UInt64, false),
+            Field::new("line", DataType::UInt64, false),
+            Field::new("white", DataType::Utf8, true),
+            Field::new("black", DataType::Utf8, true),
+            Field::new("result", DataType::Utf8, false),
+            Field::new("opening", DataType::Utf8, false),
+            Field::new("ply_count", DataType::UInt32, false),
+        ]));
+        Ok(GameTableWriter {
+            writer: FileWriter::try_new(file, &schema)?,
+            schema,
+            file: StringBuilder::new(),
+            game_index: UInt64Builder::new(),
+            line: UInt64Builder::new(),
+            white: StringBuilder::new(),
+            black: StringBuilder::new(),
+            result: StringBuilder::new(),
+            opening: StringBuilder::new(),
+            ply_count: UInt32Builder::new(),
+            row_count: 0,
+        })
+    }
+
+    /// Adds a game read from `file` at `position`, where `opening` is the key of its opening.
+    pub fn add_game(
+        &mut self,
+        file: &Path,
+        position: &GamePosition,
+        game: &Game,
+        opening: &str,
+    ) -> Result<()> {
+        let tag = |tag: &str| {
+            game.tags
+                .iter()
+                .find(|&&(ref name, _)| name == tag)
+                .map(|&(_, ref value)| value.as_str())
+        };
+
+        self.file.append_value(file.to_string_lossy());
+        self.game_index.append_value(position.game_index);
+        self.line.append_value(position.line);
+        self.white.append_option(tag("White"));
+        self.black.append_option(tag("Black"));
+        self.result.append_value(match game.termination {
+            GameTermination::WhiteWins => "1-0",
+            GameTermination::DrawnGame => "1/2-1/2",
+            GameTermination::BlackWins => "0-1",
+            GameTermination::Unknown => "*",
+        });
+        self.opening.append_value(opening);
+        self.ply_count.append_value(game.moves.len() as u32);
+
+        self.row_count += 1;
+        if self.row_count == BATCH_SIZE {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the remaining games and the footer of the file.
+    pub fn finish(mut self) -> Result<()> {
+        if self.row_count > 0 {
+            self.write_batch()?;
+        }
+        self.writer.finish()?;
+        Ok(())
+    }
+
+    fn write_batch(&mut self) -> Result<()> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.file.finish()),
+            Arc::new(self.game_index.finish()),
+            Arc::new(self.line.finish()),
+            Arc::new(self.white.finish()),
+            Arc::new(self.black.finish()),
+            Arc::new(self.result.finish()),
+            Arc::new(self.opening.finish()),
+            Arc::new(self.ply_count.finish()),
+        ];
+        self.writer
+            .write(&RecordBatch::try_new(self.schema.clone(), columns)?)?;
+        self.row_count = 0;
+        Ok(())
+    }
+}
+
+/// Writes the results of each opening as an Arrow IPC file with the full key of the opening.
+///
+/// The counts of the results are followed by their proportions of the total and the score. The
+/// results are named by `columns` in the order of `OpeningResult::counts`.
+pub fn print_openings_arrow<T: Write>(
+    file: T,
+    opening_stats: &[(&String, &OpeningResult)],
+    key: OpeningKey,
+    columns: &[&str; 3],
+) -> Result<()> {
+    let mut fields = vec![
+        Field::new(
+            if key.is_fen() { "fen" } else { "moves" },
+            DataType::Utf8,
+            false,
+        ),
+        Field::new("total", DataType::UInt32, false),
+    ];
+    for column in columns {
+        fields.push(Field::new(*column, DataType::UInt32, false));
+    }
+    for column in columns {
+        fields.push(Field::new(
+            format!("{}_proportion", column),
+            DataType::Float64,
+            false,
+        ));
+    }
+    fields.push(Field::new("score", DataType::Float64, false));
+    let schema = Arc::new(Schema::new(fields));
+
+    let counts = |count: fn(&OpeningResult) -> u32| -> ArrayRef {
+        let values: Vec<u32> = opening_stats
+            .iter()
+            .map(|&(_, result)| count(result))
+            .collect();
+        Arc::new(UInt32Array::from(values))
+    };
+    let proportions = |proportion: fn(&OpeningResult) -> f64| -> ArrayRef {
+        let values: Vec<f64> = opening_stats
+            .iter()
+            .map(|&(_, result)| proportion(result))
+            .collect();
+        Arc::new(Float64Array::from(values))
+    };
+    let openings: Vec<&str> = opening_stats
+        .iter()
+        .map(|&(opening, _)| opening.as_str())
+        .collect();
+    let arrays: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(openings)),
+        counts(OpeningResult::total_games),
+        counts(|result| result.white_win_count),
+        counts(|result| result.draw_count),
+        counts(|result| result.black_win_count),
+        proportions(OpeningResult::white_win_proportion),
+        proportions(OpeningResult::draw_proportion),
+        proportions(OpeningResult::black_win_proportion),
+        proportions(OpeningResult::score),
+    ];
+
+    let mut writer = FileWriter::try_new(file, &schema)?;
+    writer.write(&RecordBatch::try_new(schema.clone(), arrays)?)?;
+    writer.finish()?;
+    Ok(())
+}
@@ -0,0 +1,828 @@
+use std::fmt::Write;
+
+use chess_pgn_parser::{Move, Piece, Square};
+
+use {Error, ErrorKind, Result};
+
+/// The FEN of the standard starting position.
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    fn opponent(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+
+    // The direction pawns of this color move in
+    fn forward(self) -> i32 {
+        match self {
+            Color::White => 1,
+            Color::Black => -1,
+        }
+    }
+
+    fn back_rank(self) -> i32 {
+        match self {
+            Color::White => 0,
+            Color::Black => 7,
+        }
+    }
+}
+
+// The castling rights in FEN order
+const WHITE_KINGSIDE: usize = 0;
+const WHITE_QUEENSIDE: usize = 1;
+const BLACK_KINGSIDE: usize = 2;
+const BLACK_QUEENSIDE: usize = 3;
+const CASTLING_CHARS: [char; 4] = ['K', 'Q', 'k', 'q'];
+
+const KNIGHT_STEPS: [(i32, i32); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_STEPS: [(i32, i32); 8] = [
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+const ROOK_DIRECTIONS: [(i32, i32); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+
+// Squares are indexed by rank * 8 + file with a1 = 0 and h8 = 63
+type Index = usize;
+
+fn index(file: i32, rank: i32) -> Option<Index> {
+    if (0..8).contains(&file) && (0..8).contains(&rank) {
+        Some((rank * 8 + file) as Index)
+    } else {
+        None
+    }
+}
+
+fn file_of(index: Index) -> i32 {
+    (index % 8) as i32
+}
+
+fn rank_of(index: Index) -> i32 {
+    (index / 8) as i32
+}
+
+fn square_name(index: Index) -> String {
+    format!(
+        "{}{}",
+        (b'a' + file_of(index) as u8) as char,
+        rank_of(index) + 1
+    )
+}
+
+fn parse_square_name(name: &str) -> Option<Index> {
+    let bytes = name.as_bytes();
+    if bytes.len() != 2 {
+        return None;
+    }
+    index(
+        i32::from(bytes[0]) - i32::from(b'a'),
+        i32::from(bytes[1]) - i32::from(b'1'),
+    )
+}
+
+fn piece_char(piece: Piece) -> char {
+    match piece {
+        Piece::Pawn => 'p',
+        Piece::Knight => 'n',
+        Piece::Bishop => 'b',
+        Piece::Rook => 'r',
+        Piece::Queen => 'q',
+        Piece::King => 'k',
+    }
+}
+
+fn parse_piece_char(char: char) -> Option<(Color, Piece)> {
+    let piece = match char.to_ascii_lowercase() {
+        'p' => Piece::Pawn,
+        'n' => Piece::Knight,
+        'b' => Piece::Bishop,
+        'r' => Piece::Rook,
+        'q' => Piece::Queen,
+        'k' => Piece::King,
+        _ => return None,
+    };
+    let color = if char.is_ascii_uppercase() {
+        Color::White
+    } else {
+        Color::Black
+    };
+    Some((color, piece))
+}
+
+/// A chess position that SAN moves can be played on.
+#[derive(Clone, Copy)]
+pub struct Position {
+    squares: [Option<(Color, Piece)>; 64],
+    side_to_move: Color,
+    castling: [bool; 4],
+    en_passant: Option<Index>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+}
+
+impl Position {
+    /// The standard starting position.
+    pub fn start() -> Position {
+        Position::from_fen(START_FEN).expect("START_FEN is valid")
+    }
+
+    /// Parses a FEN. The halfmove clock and fullmove number fields are optional.
+    pub fn from_fen(fen: &str) -> Result<Position> {
+        let invalid = || Error::new(ErrorKind::InvalidFen(fen.to_string()));
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 || fields.len() > 6 {
+            return Err(invalid());
+        }
+
+        let mut squares = [None; 64];
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(invalid());
+        }
+        for (row, rank_text) in ranks.iter().enumerate() {
+            let rank = 7 - row as i32;
+            let mut file = 0;
+            for char in rank_text.chars() {
+                if let Some(empty_count) = char.to_digit(10) {
+                    file += empty_count as i32;
+                } else {
+                    let piece = parse_piece_char(char).ok_or_else(invalid)?;
+                    let square = index(file, rank).ok_or_else(invalid)?;
+                    squares[square] = Some(piece);
+                    file += 1;
+                }
+            }
+            if file != 8 {
+                return Err(invalid());
+            }
+        }
+
+        let side_to_move = match fields[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            _ => return Err(invalid()),
+        };
+
+        let mut castling = [false; 4];
+        if fields[2] != "-" {
+            for char in fields[2].chars() {
+                let right = CASTLING_CHARS
+                    .iter()
+                    .position(|&castling_char| castling_char == char)
+                    .ok_or_else(invalid)?;
+                castling[right] = true;
+            }
+        }
+
+        let en_passant = if fields[3] == "-" {
+            None
+        } else {
+            Some(parse_square_name(fields[3]).ok_or_else(invalid)?)
+        };
+
+        let halfmove_clock = match fields.get(4) {
+            Some(value) => value.parse().map_err(|_| invalid())?,
+            None => 0,
+        };
+        let fullmove_number = match fields.get(5) {
+            Some(value) => value.parse().map_err(|_| invalid())?,
+            None => 1,
+        };
+
+        Ok(Position {
+            squares,
+            side_to_move,
+            castling,
+            en_passant,
+            halfmove_clock,
+            fullmove_number,
+        })
+    }
+
+    /// Writes the position as a FEN with all six fields.
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for rank in (0..8).rev() {
+            let mut empty_count = 0;
+            for file in 0..8 {
+                match self.squares[index(file, rank).expect("on the board")] {
+                    Some((color, piece)) => {
+                        if empty_count > 0 {
+                            write!(fen, "{}", empty_count).expect("writing to a String");
+                            empty_count = 0;
+                        }
+                        let char = piece_char(piece);
+                        fen.push(if color == Color::White {
+                            char.to_ascii_uppercase()
+                        } else {
+                            char
+                        });
+                    }
+                    None => empty_count += 1,
+                }
+            }
+            if empty_count > 0 {
+                write!(fen, "{}", empty_count).expect("writing to a String");
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(match self.side_to_move {
+            Color::White => " w ",
+            Color::Black => " b ",
+        });
+
+        if self.castling.iter().any(|&right| right) {
+            for (&right, &char) in self.castling.iter().zip(CASTLING_CHARS.iter()) {
+                if right {
+                    fen.push(char);
+                }
+            }
+        } else {
+            fen.push('-');
+        }
+
+        match self.en_passant {
+            Some(square) => write!(fen, " {}", square_name(square)),
+            None => write!(fen, " -"),
+        }.expect("writing to a String");
+
+        write!(fen, " {} {}", self.halfmove_clock, self.fullmove_number)
+            .expect("writing to a String");
+        fen
+    }
+
+    /// Writes the position as a FEN without the halfmove clock and fullmove number.
+    ///
+    /// The en passant square is only written when an en passant capture is legal so positions
+    /// that only differ by an unusable en passant square have the same FEN.
+    pub fn to_canonical_fen(&self) -> String {
+        let mut position = *self;
+        if !position.has_legal_en_passant() {
+            position.en_passant = None;
+        }
+        let fen = position.to_fen();
+        fen.split(' ').take(4).collect::<Vec<_>>().join(" ")
+    }
+
+    /// Whether the side to move can legally capture en passant.
+    pub fn has_legal_en_passant(&self) -> bool {
+        let target = match self.en_passant {
+            Some(target) => target,
+            None => return false,
+        };
+        let color = self.side_to_move;
+        let from_rank = rank_of(target) - color.forward();
+        [file_of(target) - 1, file_of(target) + 1]
+            .iter()
+            .filter_map(|&file| index(file, from_rank))
+            .filter(|&square| self.squares[square] == Some((color, Piece::Pawn)))
+            .any(|square| {
+                self.can_move(square, target) && {
+                    let mut after = *self;
+                    after.move_piece(square, target, None);
+                    !after.in_check(color)
+                }
+            })
+    }
+
+    /// Plays a move parsed from SAN.
+    ///
+    /// Fails if the move is illegal or doesn't identify exactly one piece.
+    pub fn play(&mut self, move_: &Move) -> Result<()> {
+        let color = self.side_to_move;
+        match *move_ {
+            Move::CastleKingside => self.castle(color, true),
+            Move::CastleQueenside => self.castle(color, false),
+            Move::BasicMove {
+                piece,
+                ref to,
+                ref from,
+                promoted_to,
+                ..
+            } => {
+                let target = match (to.file(), to.rank()) {
+                    (Some(file), Some(rank)) => {
+                        index(file as i32, rank as i32).expect("on the board")
+                    }
+                    _ => {
+                        return Err(From::from(ErrorKind::IllegalMove(format!(
+                            "Bad move target {:?}",
+                            to
+                        ))))
+                    }
+                };
+
+                let candidates: Vec<Index> = (0..64)
+                    .filter(|&square| self.squares[square] == Some((color, piece)))
+                    .filter(|&square| matches_disambiguation(square, from))
+                    .filter(|&square| self.can_move(square, target))
+                    .filter(|&square| {
+                        let mut after = *self;
+                        after.move_piece(square, target, promoted_to);
+                        !after.in_check(color)
+                    })
+                    .collect();
+
+                match candidates.len() {
+                    1 => {
+                        self.move_piece(candidates[0], target, promoted_to);
+                        Ok(())
+                    }
+                    0 => Err(From::from(ErrorKind::IllegalMove(format!(
+                        "Illegal move {:?} in position {}",
+                        move_,
+                        self.to_fen()
+                    )))),
+                    _ => Err(From::from(ErrorKind::IllegalMove(format!(
+                        "Ambiguous move {:?} in position {}",
+                        move_,
+                        self.to_fen()
+                    )))),
+                }
+            }
+        }
+    }
+
+    fn castle(&mut self, color: Color, kingside: bool) -> Result<()> {
+        let rank = color.back_rank();
+        let (right, rook_file, king_to_file, rook_to_file) = match (color, kingside) {
+            (Color::White, true) => (WHITE_KINGSIDE, 7, 6, 5),
+            (Color::White, false) => (WHITE_QUEENSIDE, 0, 2, 3),
+            (Color::Black, true) => (BLACK_KINGSIDE, 7, 6, 5),
+            (Color::Black, false) => (BLACK_QUEENSIDE, 0, 2, 3),
+        };
+
+        let king_from = index(4, rank).expect("on the board");
+        let rook_from = index(rook_file, rank).expect("on the board");
+        let between_files = if kingside { 5..7 } else { 1..4 };
+        let path_is_clear = between_files
+            .map(|file| index(file, rank).expect("on the board"))
+            .all(|square| self.squares[square].is_none());
+        // The king may not castle out of, through or into check
+        let king_path_is_safe = [4, (4 + king_to_file) / 2, king_to_file]
+            .iter()
+            .map(|&file| index(file, rank).expect("on the board"))
+            .all(|square| !self.is_attacked(square, color.opponent()));
+
+        if !self.castling[right] || self.squares[king_from] != Some((color, Piece::King))
+            || self.squares[rook_from] != Some((color, Piece::Rook)) || !path_is_clear
+            || !king_path_is_safe
+        {
+            return Err(From::from(ErrorKind::IllegalMove(format!(
+                "Illegal castling in position {}",
+                self.to_fen()
+            ))));
+        }
+
+        self.squares[rook_from] = None;
+        self.squares[index(rook_to_file, rank).expect("on the board")] =
+            Some((color, Piece::Rook));
+        self.move_piece(
+            king_from,
+            index(king_to_file, rank).expect("on the board"),
+            None,
+        );
+        Ok(())
+    }
+
+    // Whether the piece on `from` can move to `to` ignoring checks to its own king
+    fn can_move(&self, from: Index, to: Index) -> bool {
+        let (color, piece) = match self.squares[from] {
+            Some(value) => value,
+            None => return false,
+        };
+        if let Some((target_color, _)) = self.squares[to] {
+            if target_color == color {
+                return false;
+            }
+        }
+
+        let file_delta = file_of(to) - file_of(from);
+        let rank_delta = rank_of(to) - rank_of(from);
+        match piece {
+            Piece::Pawn => {
+                let forward = color.forward();
+                if file_delta == 0 {
+                    let start_rank = color.back_rank() + forward;
+                    let one_step = index(file_of(from), rank_of(from) + forward);
+                    self.squares[to].is_none()
+                        && (rank_delta == forward
+                            || (rank_delta == 2 * forward && rank_of(from) == start_rank
+                                && one_step.is_some_and(|square| self.squares[square].is_none())))
+                } else {
+                    file_delta.abs() == 1 && rank_delta == forward
+                        && (self.squares[to].is_some() || self.en_passant == Some(to))
+                }
+            }
+            Piece::Knight => KNIGHT_STEPS.contains(&(file_delta, rank_delta)),
+            Piece::King => KING_STEPS.contains(&(file_delta, rank_delta)),
+            Piece::Bishop => self.slides_to(from, to, &BISHOP_DIRECTIONS),
+            Piece::Rook => self.slides_to(from, to, &ROOK_DIRECTIONS),
+            Piece::Queen => {
+                self.slides_to(from, to, &BISHOP_DIRECTIONS)
+                    || self.slides_to(from, to, &ROOK_DIRECTIONS)
+            }
+        }
+    }
+
+    fn slides_to(&self, from: Index, to: Index, directions: &[(i32, i32)]) -> bool {
+        directions.iter().any(|&(file_step, rank_step)| {
+            let mut file = file_of(from) + file_step;
+            let mut rank = rank_of(from) + rank_step;
+            while let Some(square) = index(file, rank) {
+                if square == to {
+                    return true;
+                }
+                if self.squares[square].is_some() {
+                    return false;
+                }
+                file += file_step;
+                rank += rank_step;
+            }
+            false
+        })
+    }
+
+    // Moves the piece on `from` to `to` and updates the rest of the state as if a move was played
+    fn move_piece(&mut self, from: Index, to: Index, promoted_to: Option<Piece>) {
+        let (color, piece) = self.squares[from].expect("a piece to move");
+        let is_capture = self.squares[to].is_some();
+
+        if piece == Piece::Pawn && self.en_passant == Some(to) && file_of(from) != file_of(to) {
+            let captured = index(file_of(to), rank_of(from)).expect("on the board");
+            self.squares[captured] = None;
+        }
+
+        self.en_passant = if piece == Piece::Pawn && (rank_of(to) - rank_of(from)).abs() == 2 {
+            index(file_of(from), (rank_of(from) + rank_of(to)) / 2)
+        } else {
+            None
+        };
+
+        self.squares[from] = None;
+        self.squares[to] = Some((color, promoted_to.unwrap_or(piece)));
+
+        for &(square, right) in &[
+            (4, WHITE_KINGSIDE),
+            (4, WHITE_QUEENSIDE),
+            (7, WHITE_KINGSIDE),
+            (0, WHITE_QUEENSIDE),
+            (60, BLACK_KINGSIDE),
+            (60, BLACK_QUEENSIDE),
+            (63, BLACK_KINGSIDE),
+            (56, BLACK_QUEENSIDE),
+        ] {
+            if from == square || to == square {
+                self.castling[right] = false;
+            }
+        }
+
+        if piece == Piece::Pawn || is_capture {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if color == Color::Black {
+            self.fullmove_number += 1;
+        }
+        self.side_to_move = color.opponent();
+    }
+
+    fn in_check(&self, color: Color) -> bool {
+        self.squares
+            .iter()
+            .position(|&square| square == Some((color, Piece::King)))
+            .is_some_and(|king| self.is_attacked(king, color.opponent()))
+    }
+
+    // Whether any piece of color `by` attacks `square`
+    fn is_attacked(&self, square: Index, by: Color) -> bool {
+        let file = file_of(square);
+        let rank = rank_of(square);
+        let has = |file: i32, rank: i32, pieces: &[Piece]| {
+            index(file, rank)
+                .and_then(|square| self.squares[square])
+                .is_some_and(|(color, piece)| color == by && pieces.contains(&piece))
+        };
+
+        let pawn_rank = rank - by.forward();
+        if has(file - 1, pawn_rank, &[Piece::Pawn]) || has(file + 1, pawn_rank, &[Piece::Pawn]) {
+            return true;
+        }
+        if KNIGHT_STEPS
+            .iter()
+            .any(|&(file_step, rank_step)| has(file + file_step, rank + rank_step, &[Piece::Knight]))
+        {
+            return true;
+        }
+        if KING_STEPS
+            .iter()
+            .any(|&(file_step, rank_step)| has(file + file_step, rank + rank_step, &[Piece::King]))
+        {
+            return true;
+        }
+
+        let slider_attacks = |directions: &[(i32, i32)], pieces: &[Piece]| {
+            directions.iter().any(|&(file_step, rank_step)| {
+                let mut next_file = file + file_step;
+                let mut next_rank = rank + rank_step;
+                while let Some(next) = index(next_file, next_rank) {
+                    if self.squares[next].is_some() {
+                        return has(next_file, next_rank, pieces);
+                    }
+                    next_file += file_step;
+                    next_rank += rank_step;
+                }
+                false
+            })
+        };
+        slider_attacks(&BISHOP_DIRECTIONS, &[Piece::Bishop, Piece::Queen])
+            || slider_attacks(&ROOK_DIRECTIONS, &[Piece::Rook, Piece::Queen])
+    }
+}
+
+/// Rewrites a FEN in the form returned by
+/// [`Position::to_canonical_fen`](struct.Position.html#method.to_canonical_fen).
+pub fn canonical_fen(fen: &str) -> Result<String> {
+    Ok(Position::from_fen(fen)?.to_canonical_fen())
+}
+
+// Whether `square` matches the from square given in SAN, which may only have a file or rank.
+fn matches_disambiguation(square: Index, from: &Square) -> bool {
+    from.file().is_none_or(|file| file as i32 == file_of(square))
+        && from.rank().is_none_or(|rank| rank as i32 == rank_of(square))
+}
+
+/// Writes a move parsed from SAN back as SAN without check or annotation marks.
+pub fn format_san(move_: &Move) -> String {
+    match *move_ {
+        Move::CastleKingside => "O-O".to_string(),
+        Move::CastleQueenside => "O-O-O".to_string(),
+        Move::BasicMove {
+            piece,
+            ref to,
+            ref from,
+            is_capture,
+            promoted_to,
+        } => {
+            let mut san = String::new();
+            if piece != Piece::Pawn {
+                san.push(piece_char(piece).to_ascii_uppercase());
+            }
+            if let Some(file) = from.file() {
+                san.push((b'a' + file as u8) as char);
+            }
+            if let Some(rank) = from.rank() {
+                san.push((b'1' + rank as u8) as char);
+            }
+            if is_capture {
+                san.push('x');
+            }
+            if let (Some(file), Some(rank)) = (to.file(), to.rank()) {
+                san.push_str(&square_name(
+                    index(file as i32, rank as i32).expect("on the board"),
+                ));
+            }
+            if let Some(piece) = promoted_to {
+                san.push('=');
+                san.push(piece_char(piece).to_ascii_uppercase());
+            }
+            san
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chess_pgn_parser::read_games;
+
+    // Plays the moves of `movetext` from `fen` and returns the FEN of the position reached
+    fn play(fen: &str, movetext: &str) -> Result<String> {
+        let games = read_games(&format!("{} *\n", movetext)).expect("The moves are valid PGN");
+        let mut position = Position::from_fen(fen)?;
+        for game_move in &games[0].moves {
+            position.play(&game_move.move_.move_)?;
+        }
+        Ok(position.to_fen())
+    }
+
+    fn is_illegal(result: Result<String>) -> bool {
+        match result {
+            Err(ref err) => matches!(*err.kind(), ErrorKind::IllegalMove(_)),
+            Ok(_) => false,
+        }
+    }
+
+    #[test]
+    fn fens_round_trip() {
+        for fen in &[
+            START_FEN,
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ] {
+            assert_eq!(Position::from_fen(fen).expect("The FEN is valid").to_fen(), *fen);
+        }
+    }
+
+    #[test]
+    fn fens_without_counters_get_defaults() {
+        let position = Position::from_fen("8/8/8/8/8/8/8/K6k w - -").expect("The FEN is valid");
+        assert_eq!(position.to_fen(), "8/8/8/8/8/8/8/K6k w - - 0 1");
+    }
+
+    #[test]
+    fn invalid_fens_are_rejected() {
+        for fen in &[
+            "",
+            "8/8/8/8/8/8/8 w - - 0 1",
+            "8/8/8/8/8/8/8/K6k x - - 0 1",
+            "8/8/8/8/8/8/8/K6X w - - 0 1",
+            "8/8/8/8/8/8/8/K5k w - - 0 1",
+            "8/8/8/8/8/8/8/K6k w - z9 0 1",
+        ] {
+            match Position::from_fen(fen) {
+                Err(ref err) => match *err.kind() {
+                    ErrorKind::InvalidFen(ref invalid) => assert_eq!(invalid, fen),
+                    ref kind => panic!("Unexpected error {:?}", kind),
+                },
+                Ok(_) => panic!("{} is valid", fen),
+            }
+        }
+    }
+
+    #[test]
+    fn canonical_fens_drop_the_counters() {
+        assert_eq!(
+            canonical_fen(START_FEN).expect("The FEN is valid"),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -"
+        );
+        assert_eq!(
+            canonical_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 12 40")
+                .expect("The FEN is valid"),
+            canonical_fen(START_FEN).expect("The FEN is valid")
+        );
+    }
+
+    #[test]
+    fn canonical_fens_keep_usable_en_passant_squares() {
+        assert_eq!(
+            canonical_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2").expect("The FEN is valid"),
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6"
+        );
+    }
+
+    #[test]
+    fn canonical_fens_drop_en_passant_squares_without_a_capturing_pawn() {
+        assert_eq!(
+            canonical_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1")
+                .expect("The FEN is valid"),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq -"
+        );
+        // A pawn on the wrong file can't capture
+        assert_eq!(
+            canonical_fen("4k3/8/8/3p2P1/8/8/8/4K3 w - d6 0 2").expect("The FEN is valid"),
+            "4k3/8/8/3p2P1/8/8/8/4K3 w - -"
+        );
+    }
+
+    #[test]
+    fn canonical_fens_drop_en_passant_squares_of_pinned_pawns() {
+        assert_eq!(
+            canonical_fen("4r1k1/8/8/3pP3/8/8/8/4K3 w - d6 0 2").expect("The FEN is valid"),
+            "4r1k1/8/8/3pP3/8/8/8/4K3 w - -"
+        );
+        // Both pawns leave the rank of the king
+        assert_eq!(
+            canonical_fen("8/8/8/KPp4r/8/8/8/6k1 w - c6 0 2").expect("The FEN is valid"),
+            "8/8/8/KPp4r/8/8/8/6k1 w - -"
+        );
+    }
+
+    #[test]
+    fn plays_an_opening() {
+        assert_eq!(
+            play(START_FEN, "1. e4 c5 2. Nf3").expect("The moves are legal"),
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+    }
+
+    #[test]
+    fn disambiguates_by_file_and_rank() {
+        let knights = "4k3/8/8/8/8/5N2/8/1N5K w - - 0 1";
+        assert!(is_illegal(play(knights, "Nd2")));
+        assert_eq!(
+            play(knights, "Nbd2").expect("The move is legal"),
+            "4k3/8/8/8/8/5N2/3N4/7K b - - 1 1"
+        );
+        assert_eq!(
+            play(knights, "Nfd2").expect("The move is legal"),
+            "4k3/8/8/8/8/8/3N4/1N5K b - - 1 1"
+        );
+        assert_eq!(
+            play("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "R1a3").expect("The move is legal"),
+            "4k3/8/8/R7/8/R7/8/4K3 b - - 1 1"
+        );
+    }
+
+    #[test]
+    fn pinned_pieces_dont_make_a_move_ambiguous() {
+        assert_eq!(
+            play("4k3/8/8/3b4/8/5N2/8/1N5K w - - 0 1", "Nd2").expect("The move is legal"),
+            "4k3/8/8/3b4/8/5N2/3N4/7K b - - 1 1"
+        );
+    }
+
+    #[test]
+    fn promotes() {
+        assert_eq!(
+            play("8/P3k3/8/8/8/8/8/4K3 w - - 0 1", "a8=Q").expect("The move is legal"),
+            "Q7/4k3/8/8/8/8/8/4K3 b - - 0 1"
+        );
+        assert_eq!(
+            play("1r6/P3k3/8/8/8/8/8/4K3 w - - 5 1", "axb8=N").expect("The move is legal"),
+            "1N6/4k3/8/8/8/8/8/4K3 b - - 0 1"
+        );
+    }
+
+    #[test]
+    fn castles() {
+        let rooks = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(
+            play(rooks, "O-O O-O-O").expect("The moves are legal"),
+            "2kr3r/8/8/8/8/8/8/R4RK1 w - - 2 2"
+        );
+        assert!(is_illegal(play("4k3/8/8/8/8/8/8/R3K2R w - - 0 1", "O-O")));
+        assert!(is_illegal(play("4k3/8/8/8/8/8/8/R3KN1R w KQ - 0 1", "O-O")));
+    }
+
+    #[test]
+    fn doesnt_castle_out_of_or_through_check() {
+        assert!(is_illegal(play("4r1k1/8/8/8/8/8/8/R3K2R w KQ - 0 1", "O-O")));
+        assert!(is_illegal(play("5rk1/8/8/8/8/8/8/R3K2R w KQ - 0 1", "O-O")));
+        assert!(is_illegal(play("6rk/8/8/8/8/8/8/R3K2R w KQ - 0 1", "O-O")));
+        // Only the squares the king crosses need to be safe
+        assert_eq!(
+            play("1r4k1/8/8/8/8/8/8/R3K3 w Q - 0 1", "O-O-O").expect("The move is legal"),
+            "1r4k1/8/8/8/8/8/8/2KR4 b - - 1 1"
+        );
+    }
+
+    #[test]
+    fn captures_en_passant() {
+        let fen = play(START_FEN, "1. e4 Nf6 2. e5 d5").expect("The moves are legal");
+        assert_eq!(
+            fen,
+            "rnbqkb1r/ppp1pppp/5n2/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3"
+        );
+        assert_eq!(
+            play(&fen, "exd6").expect("The move is legal"),
+            "rnbqkb1r/ppp1pppp/3P1n2/8/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 3"
+        );
+        // The en passant square is only available for one move
+        assert!(is_illegal(play(&fen, "Nf3 Nc6 exd6")));
+    }
+
+    #[test]
+    fn moves_leaving_the_king_in_check_are_illegal() {
+        let rook = "4k3/8/8/8/8/8/4r3/4K3 w - - 0 1";
+        assert!(is_illegal(play(rook, "Kd2")));
+        assert_eq!(
+            play(rook, "Kxe2").expect("The move is legal"),
+            "4k3/8/8/8/8/8/4K3/8 b - - 0 1"
+        );
+        // The bishop is pinned to the king
+        assert!(is_illegal(play("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1", "Bd3")));
+    }
+}
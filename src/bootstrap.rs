@@ -0,0 +1,324 @@
+use std::f64::INFINITY;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+
+use stats::{fit_polya, FitOptions, InitialAlpha, PolyaFit, StopReason};
+
+/// The largest share of the replicates whose fit can fail before the standard errors and
+/// intervals are withheld, as leaving out that many would make them too narrow.
+pub const MAX_FAILED_SHARE: f64 = 0.05;
+
+/// The options of [`bootstrap`](fn.bootstrap.html).
+#[derive(Clone, Copy, Debug)]
+pub struct BootstrapOptions {
+    /// The number of times the openings are resampled and the distribution refitted.
+    pub replicates: u32,
+    /// The seed of the random number generator. The same seed gives the same results whatever
+    /// the number of threads.
+    pub seed: u64,
+    /// Whether the games of each resampled opening are also resampled from its results. This
+    /// adds to the variation between the openings so it biases alpha and its sum downwards.
+    pub resample_games: bool,
+    /// The confidence level of the intervals e.g. 0.95.
+    pub confidence_level: f64,
+}
+
+impl Default for BootstrapOptions {
+    fn default() -> BootstrapOptions {
+        BootstrapOptions {
+            replicates: 1000,
+            seed: 0,
+            resample_games: false,
+            confidence_level: 0.95,
+        }
+    }
+}
+
+/// A statistic of a book with its bootstrap confidence interval.
+#[derive(Clone, Debug)]
+pub struct BootstrapStatistic {
+    pub name: String,
+    /// The value of the statistic for the book itself.
+    pub estimate: f64,
+    /// The standard deviation of the statistic over the replicates. There is none with fewer
+    /// than two replicates, when the statistic is infinite in a replicate or when too many
+    /// replicates failed.
+    pub standard_error: Option<f64>,
+    /// The percentile interval of the statistic over the replicates, which has an infinite upper
+    /// bound when enough replicates have an infinite alpha. There is none without replicates or
+    /// when too many replicates failed.
+    pub interval: Option<(f64, f64)>,
+}
+
+/// The results of [`bootstrap`](fn.bootstrap.html).
+#[derive(Clone, Debug)]
+pub struct Bootstrap {
+    pub statistics: Vec<BootstrapStatistic>,
+    pub replicates: u32,
+    /// The number of replicates whose alpha grows without bound because their openings vary no
+    /// more than their games do. Their alpha and its sum are counted as infinite.
+    pub unbounded_replicates: u32,
+    /// The number of replicates that were left out because their fit didn't converge.
+    pub failed_replicates: u32,
+    pub confidence_level: f64,
+}
+
+impl Bootstrap {
+    /// Whether more than [`MAX_FAILED_SHARE`](constant.MAX_FAILED_SHARE.html) of the replicates
+    /// failed so the standard errors and intervals are withheld.
+    pub fn too_many_failures(&self) -> bool {
+        too_many_failures(self.failed_replicates, self.replicates)
+    }
+}
+
+/// Estimates the uncertainty of the statistics of a book by resampling its openings with
+/// replacement and refitting the Dirichlet distribution with
+/// [`fit_polya`](../stats/fn.fit_polya.html) for each replicate.
+///
+/// The samples are in the order of `fit_polya` and `fit` is their fit. Each replicate is fitted
+/// with `fit_options` starting from the alpha of `fit`. The statistics are alpha, its sum, the
+/// mean proportions of the results, the variance of the proportions of the openings of each
+/// result implied by the fit, the proportion of draws and the score of White (or the player) in
+/// all the games. Results are named by `columns`.
+///
+/// A replicate whose alpha grows without bound has an infinite alpha and the proportions of its
+/// games as its mean. Replicates whose fit fails otherwise are left out, unless there are too
+/// many of them for the standard errors and intervals to be trusted.
+///
+/// The replicates are run in parallel, each with its own stream of the random number
+/// generator so they don't depend on the order they're run in.
+pub fn bootstrap(
+    samples: &[[u32; 3]],
+    fit: &PolyaFit,
+    fit_options: &FitOptions,
+    options: &BootstrapOptions,
+    columns: &[&str; 3],
+) -> Bootstrap {
+    let fit_options = FitOptions {
+        initial_alpha: InitialAlpha::Value(fit.alpha),
+        ..*fit_options
+    };
+
+    let replicates: Vec<Option<Vec<f64>>> = (0..options.replicates)
+        .into_par_iter()
+        .map(|replicate| {
+            let mut rng = ChaCha8Rng::seed_from_u64(options.seed);
+            rng.set_stream(u64::from(replicate));
+            let resampled = resample(samples, options.resample_games, &mut rng);
+            let fit = fit_polya(&resampled, &fit_options);
+            match fit.stop_reason {
+                StopReason::Converged => Some(statistics(&resampled, &fit.alpha)),
+                StopReason::Unbounded => Some(statistics(&resampled, &[INFINITY; 3])),
+                _ => None,
+            }
+        })
+        .collect();
+    let replicates: Vec<Vec<f64>> = replicates.into_iter().flatten().collect();
+    let failed_replicates = options.replicates - replicates.len() as u32;
+    let unbounded_replicates = replicates
+        .iter()
+        .filter(|values| values[3].is_infinite())
+        .count() as u32;
+    let reported = !too_many_failures(failed_replicates, options.replicates);
+
+    let tail = (1. - options.confidence_level) / 2.;
+    let statistics = statistic_names(columns)
+        .into_iter()
+        .zip(statistics(samples, &fit.alpha))
+        .enumerate()
+        .map(|(index, (name, estimate))| {
+            let mut values: Vec<f64> = replicates.iter().map(|values| values[index]).collect();
+            values.sort_by(|left, right| left.partial_cmp(right).expect("Values aren't NaN"));
+            let interval = if reported && !values.is_empty() {
+                Some((quantile(&values, tail), quantile(&values, 1. - tail)))
+            } else {
+                None
+            };
+            BootstrapStatistic {
+                name,
+                estimate,
+                standard_error: if reported { standard_deviation(&values) } else { None },
+                interval,
+            }
+        })
+        .collect();
+
+    Bootstrap {
+        statistics,
+        replicates: options.replicates,
+        unbounded_replicates,
+        failed_replicates,
+        confidence_level: options.confidence_level,
+    }
+}
+
+fn too_many_failures(failed_replicates: u32, replicates: u32) -> bool {
+    f64::from(failed_replicates) > MAX_FAILED_SHARE * f64::from(replicates)
+}
+
+// Draws as many openings as there are from the samples with replacement and, if
+// `resample_games` is set, draws the results of the games of each opening from its proportions
+fn resample<R: Rng>(samples: &[[u32; 3]], resample_games: bool, rng: &mut R) -> Vec<[u32; 3]> {
+    (0..samples.len())
+        .map(|_| {
+            let sample = samples[rng.gen_range(0..samples.len())];
+            if !resample_games {
+                return sample;
+            }
+            let total = sample.iter().sum::<u32>();
+            let mut counts = [0; 3];
+            for _ in 0..total {
+                let game = rng.gen_range(0..total);
+                let result = if game < sample[0] {
+                    0
+                } else if game < sample[0] + sample[1] {
+                    1
+                } else {
+                    2
+                };
+                counts[result] += 1;
+            }
+            counts
+        })
+        .collect()
+}
+
+fn statistic_names(columns: &[&str; 3]) -> Vec<String> {
+    let mut names: Vec<String> = columns
+        .iter()
+        .map(|column| format!("alpha_{}", column))
+        .collect();
+    names.push("alpha_sum".to_string());
+    names.extend(columns.iter().map(|column| format!("mean_{}", column)));
+    names.extend(columns.iter().map(|column| format!("variance_{}", column)));
+    names.push("draw_rate".to_string());
+    names.push("score".to_string());
+    names
+}
+
+// The statistics in the order of `statistic_names`. An infinite alpha has the mean of the
+// proportions of all the games, which is its limit, and no variance.
+fn statistics(samples: &[[u32; 3]], alpha: &[f64; 3]) -> Vec<f64> {
+    let mut totals = [0.; 3];
+    for sample in samples {
+        for index in 0..3 {
+            totals[index] += f64::from(sample[index]);
+        }
+    }
+    let games = totals.iter().sum::<f64>();
+
+    let alpha_sum = alpha.iter().sum::<f64>();
+    let mut values = alpha.to_vec();
+    values.push(alpha_sum);
+    if alpha_sum.is_infinite() {
+        values.extend(totals.iter().map(|total| total / games));
+        values.extend(&[0.; 3]);
+    } else {
+        values.extend(alpha.iter().map(|alpha| alpha / alpha_sum));
+        // The variance of a Dirichlet distribution's marginals
+        values.extend(alpha.iter().map(|alpha| {
+            let mean = alpha / alpha_sum;
+            mean * (1. - mean) / (alpha_sum + 1.)
+        }));
+    }
+    values.push(totals[1] / games);
+    values.push((totals[0] + totals[1] / 2.) / games);
+    values
+}
+
+// The sample standard deviation of the values, if there are at least two and they're finite
+fn standard_deviation(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 || values.iter().any(|value| value.is_infinite()) {
+        return None;
+    }
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    let variance = values
+        .iter()
+        .map(|value| (value - mean) * (value - mean))
+        .sum::<f64>() / (values.len() as f64 - 1.);
+    Some(variance.sqrt())
+}
+
+// The quantile of sorted values, which mustn't be empty, interpolating between them. It's
+// infinite when the value above it is.
+fn quantile(values: &[f64], probability: f64) -> f64 {
+    let position = probability * (values.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    if lower == upper || values[lower] == values[upper] {
+        values[lower]
+    } else {
+        values[lower] + (values[upper] - values[lower]) * (position - lower as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantile_interpolates() {
+        let values = [1., 2., 4.];
+        assert_eq!(quantile(&values, 0.), 1.);
+        assert_eq!(quantile(&values, 0.25), 1.5);
+        assert_eq!(quantile(&values, 0.75), 3.);
+        assert_eq!(quantile(&values, 1.), 4.);
+        assert_eq!(quantile(&[3.], 0.5), 3.);
+    }
+
+    #[test]
+    fn quantile_of_infinite_values() {
+        let values = [1., 2., INFINITY, INFINITY];
+        assert_eq!(quantile(&values, 0.5), INFINITY);
+        assert_eq!(quantile(&values, 1.), INFINITY);
+        assert_eq!(quantile(&values, 1. / 3.), 2.);
+    }
+
+    #[test]
+    fn standard_deviation_needs_two_finite_values() {
+        assert_eq!(standard_deviation(&[]), None);
+        assert_eq!(standard_deviation(&[1.]), None);
+        assert_eq!(standard_deviation(&[1., INFINITY]), None);
+        assert_eq!(standard_deviation(&[1., 3.]), Some(2f64.sqrt()));
+    }
+
+    #[test]
+    fn unbounded_replicates_count_as_infinite() {
+        // A single opening never has a fit
+        let samples = [[3, 4, 3]];
+        let fit = PolyaFit::with_alpha([1.; 3]);
+        let options = BootstrapOptions {
+            replicates: 20,
+            ..BootstrapOptions::default()
+        };
+        let bootstrap = bootstrap(&samples, &fit, &FitOptions::default(), &options, &["w"; 3]);
+        assert_eq!(bootstrap.unbounded_replicates, 20);
+        assert_eq!(bootstrap.failed_replicates, 0);
+        let alpha_sum = &bootstrap.statistics[3];
+        assert_eq!(alpha_sum.standard_error, None);
+        assert_eq!(alpha_sum.interval, Some((INFINITY, INFINITY)));
+        let mean_draw = &bootstrap.statistics[5];
+        assert!(mean_draw.standard_error.unwrap() < 1e-12);
+        assert_eq!(mean_draw.interval, Some((0.4, 0.4)));
+    }
+
+    #[test]
+    fn failed_replicates_withhold_intervals() {
+        // Every resampled book has only draws
+        let samples = [[0, 4, 0], [0, 2, 0]];
+        let fit = PolyaFit::with_alpha([1.; 3]);
+        let options = BootstrapOptions {
+            replicates: 20,
+            ..BootstrapOptions::default()
+        };
+        let bootstrap = bootstrap(&samples, &fit, &FitOptions::default(), &options, &["w"; 3]);
+        assert_eq!(bootstrap.failed_replicates, 20);
+        assert!(bootstrap.too_many_failures());
+        for statistic in &bootstrap.statistics {
+            assert_eq!(statistic.standard_error, None);
+            assert_eq!(statistic.interval, None);
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use chess_pgn_parser::{Game, GameTermination};
+use rusqlite::Connection;
+use serde_json::{Map, Value};
+
+use filter::{TagFilter, TagMatcher};
+use matchup::MatchupGrouping;
+use output::{SortBy, StatsFormat};
+use pgn::GamePosition;
+use stats::{FitMethod, InitialAlpha, PolyaFit};
+use {OpeningKey, OpeningResult, Options, Perspective, Result, UnfinishedPolicy};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started TEXT NOT NULL,
+    output TEXT NOT NULL,
+    options TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS input_files (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    path TEXT NOT NULL,
+    games INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS games (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    file TEXT NOT NULL,
+    game_index INTEGER NOT NULL,
+    line INTEGER NOT NULL,
+    white TEXT,
+    black TEXT,
+    result TEXT NOT NULL,
+    opening TEXT NOT NULL,
+    ply_count INTEGER NOT NULL,
+    tags TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS openings (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    matchup TEXT,
+    opening TEXT NOT NULL,
+    total INTEGER NOT NULL,
+    white_win INTEGER NOT NULL,
+    draw INTEGER NOT NULL,
+    black_win INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS fits (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    matchup TEXT,
+    openings INTEGER NOT NULL,
+    alpha_white_win REAL NOT NULL,
+    alpha_draw REAL NOT NULL,
+    alpha_black_win REAL NOT NULL,
+    log_likelihood REAL NOT NULL
+);
+";
+
+/// Writes the games, openings and fits of an analysis to an SQLite database.
+///
+/// The tables are created if the database doesn't have them yet and each analysis is added as a
+/// new run so the results of many analyses can be kept in one database. Every row has the id of
+/// its run and the options of the run are kept in the `runs` table as a JSON object with the
+/// values of the command line options. Nothing is written until
+/// [`finish`](#method.finish) is called.
+///
+/// With `Perspective::Player` the `white_win` and `black_win` columns hold the wins and losses
+/// of the player.
+pub struct Database {
+    connection: Connection,
+    run_id: i64,
+}
+
+impl Database {
+    /// Opens or creates the database at `path` and starts a new run.
+    ///
+    /// `output` is the output directory of the run and `options` its options.
+    pub fn open(path: &Path, output: &str, options: &Options) -> Result<Database> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        connection.execute_batch("BEGIN")?;
+        connection.execute(
+            "INSERT INTO runs (started, output, options) VALUES (datetime('now'), ?1, ?2)",
+            params![output, options_json(options).to_string()],
+        )?;
+        let run_id = connection.last_insert_rowid();
+        Ok(Database { connection, run_id })
+    }
+
+    /// The id of the run in the database.
+    pub fn run_id(&self) -> i64 {
+        self.run_id
+    }
+
+    pub fn add_input_file(&mut self, path: &Path, game_count: u32) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO input_files (run_id, path, games) VALUES (?1, ?2, ?3)",
+            params![self.run_id, path.to_string_lossy(), game_count],
+        )?;
+        Ok(())
+    }
+
+    /// Adds a game read from `file` at `position`, where `opening` is the key of its opening.
+    /// The tags are written as a JSON object.
+    pub fn add_game(
+        &mut self,
+        file: &Path,
+        position: &GamePosition,
+        game: &Game,
+        opening: &str,
+    ) -> Result<()> {
+        let tag = |tag: &str| {
+            game.tags
+                .iter()
+                .find(|&&(ref name, _)| name == tag)
+                .map(|&(_, ref value)| value.clone())
+        };
+        let result = match game.termination {
+            GameTermination::WhiteWins => "1-0",
+            GameTermination::DrawnGame => "1/2-1/2",
+            GameTermination::BlackWins => "0-1",
+            GameTermination::Unknown => "*",
+        };
+        let tags: Map<String, Value> = game.tags
+            .iter()
+            .map(|&(ref name, ref value)| (name.clone(), value.as_str().into()))
+            .collect();
+
+        let mut statement = self.connection.prepare_cached(
+            "INSERT INTO games (run_id, file, game_index, line, white, black, result, opening, \
+             ply_count, tags) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        )?;
+        statement.execute(params![
+            self.run_id,
+            file.to_string_lossy(),
+            position.game_index as i64,
+            position.line as i64,
+            tag("White"),
+            tag("Black"),
+            result,
+            opening,
+            game.moves.len() as i64,
+            Value::Object(tags).to_string(),
+        ])?;
+        Ok(())
+    }
+
+    /// Adds the results of the openings of all the games, or of the games of a matchup.
+    pub fn add_openings(
+        &mut self,
+        matchup: Option<&str>,
+        opening_stats: &HashMap<String, OpeningResult>,
+    ) -> Result<()> {
+        let mut statement = self.connection.prepare_cached(
+            "INSERT INTO openings (run_id, matchup, opening, total, white_win, draw, black_win) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?;
+        for (opening, result) in opening_stats {
+            statement.execute(params![
+                self.run_id,
+                matchup,
+                opening,
+                result.total_games(),
+                result.white_win_count,
+                result.draw_count,
+                result.black_win_count,
+            ])?;
+        }
+        Ok(())
+    }
+
+    /// Adds the Dirichlet alpha fitted to the openings of all the games, or of the games of a
+    /// matchup, with its log-likelihood.
+    pub fn add_fit(
+        &mut self,
+        matchup: Option<&str>,
+        opening_stats: &HashMap<String, OpeningResult>,
+        fit: &PolyaFit,
+    ) -> Result<()> {
+        let alpha = &fit.alpha;
+        self.connection.execute(
+            "INSERT INTO fits (run_id, matchup, openings, alpha_white_win, alpha_draw, \
+             alpha_black_win, log_likelihood) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                self.run_id,
+                matchup,
+                opening_stats.len() as i64,
+                alpha[0],
+                alpha[1],
+                alpha[2],
+                fit.log_likelihood,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Commits the run to the database.
+    pub fn finish(self) -> Result<()> {
+        self.connection.execute_batch("COMMIT")?;
+        Ok(())
+    }
+}
+
+// The options of a run as a JSON object named and valued like the command line options
+fn options_json(options: &Options) -> Value {
+    let tag_filters = |filters: &[TagFilter]| -> Vec<String> {
+        filters
+            .iter()
+            .map(|filter| match filter.matcher {
+                TagMatcher::Exact(ref value) => format!("{}={}", filter.tag, value),
+                TagMatcher::Regex(ref regex) => format!("{}~{}", filter.tag, regex.as_str()),
+            })
+            .collect()
+    };
+    let aggregation = &options.aggregation;
+    let (opening_key, plies) = match aggregation.key {
+        OpeningKey::Fen => ("fen", None),
+        OpeningKey::Moves(plies) => ("moves", Some(plies)),
+        OpeningKey::Position(plies) => ("position", Some(plies)),
+    };
+    let fit = &options.fit;
+
+    json!({
+        "include": tag_filters(&options.filter.include),
+        "exclude": tag_filters(&options.filter.exclude),
+        "date_from": options.filter.date_from,
+        "date_to": options.filter.date_to,
+        "opening_key": opening_key,
+        "plies": plies,
+        "raw_fen": !aggregation.normalize_fen,
+        "unfinished": match aggregation.unfinished {
+            UnfinishedPolicy::Skip => "skip",
+            UnfinishedPolicy::Error => "error",
+            UnfinishedPolicy::Draw => "draw",
+        },
+        "player": match aggregation.perspective {
+            Perspective::Colour => None,
+            Perspective::Player(ref player) => Some(player.as_str()),
+        },
+        "lenient": options.lenient,
+        "matchups": options.matchups.map(|grouping| match grouping {
+            MatchupGrouping::Ordered => "ordered",
+            MatchupGrouping::Unordered => "unordered",
+        }),
+        "pairs": options.pairs,
+        "openings_json": options.openings_json,
+        "stats_format": match options.stats_format {
+            StatsFormat::Proportions => "proportions",
+            StatsFormat::Detailed => "detailed",
+        },
+        "sort_by": match options.sort_by {
+            SortBy::Key => "fen",
+            SortBy::Total => "total",
+            SortBy::DrawRate => "draw-rate",
+            SortBy::Score => "score",
+            SortBy::Imbalance => "imbalance",
+            SortBy::Count => "count",
+        },
+        "arrow": options.arrow,
+        "fit_tolerance": fit.tolerance,
+        "fit_max_iterations": fit.max_iterations,
+        "fit_initial_alpha": match fit.initial_alpha {
+            InitialAlpha::Value(alpha) => json!(alpha),
+            InitialAlpha::Moments => json!("moments"),
+        },
+        "fit_method": match fit.method {
+            FitMethod::FixedPoint => "fixed-point",
+            FitMethod::Newton => "newton",
+        },
+        "bootstrap": options.bootstrap.map(|bootstrap| bootstrap.replicates),
+        "bootstrap_seed": options.bootstrap.map(|bootstrap| bootstrap.seed),
+        "bootstrap_games": options.bootstrap.map(|bootstrap| bootstrap.resample_games),
+        "goodness_of_fit": options.goodness_of_fit.is_some(),
+        "goodness_of_fit_replicates": options
+            .goodness_of_fit
+            .map(|goodness_of_fit| goodness_of_fit.replicates),
+    })
+}
@@ -0,0 +1,256 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use arrow_schema::ArrowError;
+use glob::{GlobError, PatternError};
+use rusqlite;
+use serde_json;
+
+use pgn::GamePosition;
+
+/// The kinds of error that can stop an analysis.
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// Reading an input or writing an output failed.
+    Io(io::Error),
+    /// A game isn't valid PGN.
+    Parse,
+    /// The text of a game isn't valid UTF-8.
+    InvalidUtf8,
+    /// A game doesn't have a FEN tag when openings are identified by FEN.
+    MissingFen,
+    /// A game has more than one FEN tag.
+    DuplicateFen,
+    /// A game didn't end in a win, draw or loss.
+    BadTermination,
+    /// A FEN couldn't be parsed.
+    InvalidFen(String),
+    /// A move couldn't be played.
+    IllegalMove(String),
+    /// The Dirichlet fit didn't converge.
+    NotConverged(String),
+    Other(String),
+}
+
+impl ErrorKind {
+    /// Whether the error is caused by the contents of a single game.
+    pub fn is_game_error(&self) -> bool {
+        !matches!(
+            *self,
+            ErrorKind::Io(_) | ErrorKind::NotConverged(_) | ErrorKind::Other(_)
+        )
+    }
+
+    /// A short description of the kind of error without any details.
+    pub fn summary(&self) -> &'static str {
+        match *self {
+            ErrorKind::Io(_) => "I/O error",
+            ErrorKind::Parse => "PGN parse error",
+            ErrorKind::InvalidUtf8 => "Invalid UTF-8",
+            ErrorKind::MissingFen => "FEN tag not found",
+            ErrorKind::DuplicateFen => "Too many FEN tags found",
+            ErrorKind::BadTermination => "Bad game termination found",
+            ErrorKind::InvalidFen(_) => "Invalid FEN",
+            ErrorKind::IllegalMove(_) => "Illegal move",
+            ErrorKind::NotConverged(_) => "Fit didn't converge",
+            ErrorKind::Other(_) => "Other error",
+        }
+    }
+
+    /// The exit code of the command line utility for this kind of error.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            ErrorKind::Other(_) => 1,
+            ErrorKind::Io(_) => 2,
+            ErrorKind::Parse => 3,
+            ErrorKind::MissingFen => 4,
+            ErrorKind::DuplicateFen => 5,
+            ErrorKind::BadTermination => 6,
+            ErrorKind::InvalidFen(_) => 7,
+            ErrorKind::IllegalMove(_) => 8,
+            ErrorKind::NotConverged(_) => 9,
+            ErrorKind::InvalidUtf8 => 10,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::Io(ref err) => write!(f, "I/O error: {}", err),
+            ErrorKind::Parse => write!(f, "PGN parse error"),
+            ErrorKind::InvalidUtf8 => write!(f, "Invalid UTF-8"),
+            ErrorKind::MissingFen => write!(f, "FEN tag not found"),
+            ErrorKind::DuplicateFen => write!(f, "Too many FEN tags found"),
+            ErrorKind::BadTermination => write!(f, "Bad game termination found"),
+            ErrorKind::InvalidFen(ref fen) => write!(f, "Invalid FEN '{}'", fen),
+            ErrorKind::IllegalMove(ref message)
+            | ErrorKind::NotConverged(ref message)
+            | ErrorKind::Other(ref message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
+/// An error with the location in the input where it happened, when that is known.
+#[derive(Debug)]
+pub struct Error {
+    // Boxed so results stay small when there's no error
+    inner: Box<ErrorInner>,
+}
+
+#[derive(Debug)]
+struct ErrorInner {
+    kind: ErrorKind,
+    file: Option<PathBuf>,
+    position: Option<GamePosition>,
+    tag: Option<(String, String)>,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind) -> Error {
+        Error {
+            inner: Box::new(ErrorInner {
+                kind,
+                file: None,
+                position: None,
+                tag: None,
+            }),
+        }
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.inner.kind
+    }
+
+    /// The input file the error happened in.
+    pub fn file(&self) -> Option<&Path> {
+        self.inner.file.as_deref()
+    }
+
+    /// The position of the game the error happened in.
+    pub fn position(&self) -> Option<&GamePosition> {
+        self.inner.position.as_ref()
+    }
+
+    /// The name and value of the tag that caused the error.
+    pub fn tag(&self) -> Option<&(String, String)> {
+        self.inner.tag.as_ref()
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        self.inner.kind.exit_code()
+    }
+
+    /// Sets the input file unless it's already set.
+    pub fn in_file(mut self, file: &Path) -> Error {
+        if self.inner.file.is_none() {
+            self.inner.file = Some(file.to_path_buf());
+        }
+        self
+    }
+
+    /// Sets the position of the game unless it's already set.
+    pub fn at(mut self, position: &GamePosition) -> Error {
+        if self.inner.position.is_none() {
+            self.inner.position = Some(position.clone());
+        }
+        self
+    }
+
+    pub fn with_tag(mut self, name: &str, value: &str) -> Error {
+        self.inner.tag = Some((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.inner.kind)?;
+        if let Some(ref position) = self.inner.position {
+            write!(
+                f,
+                " in game {} at line {} (byte {})",
+                position.game_index, position.line, position.byte
+            )?;
+        }
+        if let Some(ref file) = self.inner.file {
+            let preposition = if self.inner.position.is_some() { "of" } else { "in" };
+            write!(f, " {} {}", preposition, file.display())?;
+        }
+        if let Some((ref name, ref value)) = self.inner.tag {
+            write!(f, ": [{} \"{}\"]", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        "cetoba error"
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match self.inner.kind {
+            ErrorKind::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error::new(kind)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::new(ErrorKind::Io(err))
+    }
+}
+
+impl From<GlobError> for Error {
+    fn from(err: GlobError) -> Error {
+        Error::new(ErrorKind::Io(err.into()))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        Error::new(ErrorKind::Io(err.into()))
+    }
+}
+
+impl From<ArrowError> for Error {
+    fn from(err: ArrowError) -> Error {
+        Error::new(ErrorKind::Io(io::Error::other(err)))
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Error {
+        Error::new(ErrorKind::Io(io::Error::other(err)))
+    }
+}
+
+impl From<PatternError> for Error {
+    fn from(err: PatternError) -> Error {
+        Error::new(ErrorKind::Other(err.to_string()))
+    }
+}
+
+impl<'a> From<&'a str> for Error {
+    fn from(message: &'a str) -> Error {
+        Error::new(ErrorKind::Other(message.to_string()))
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        Error::new(ErrorKind::Other(message))
+    }
+}
@@ -0,0 +1,134 @@
+use chess_pgn_parser::Game;
+use regex::Regex;
+
+use Result;
+
+/// How the value of a tag is matched.
+#[derive(Clone, Debug)]
+pub enum TagMatcher {
+    /// The value must be equal to the string.
+    Exact(String),
+    /// The value must contain a match of the regular expression.
+    Regex(Regex),
+}
+
+impl TagMatcher {
+    pub fn is_match(&self, value: &str) -> bool {
+        match *self {
+            TagMatcher::Exact(ref expected) => value == expected,
+            TagMatcher::Regex(ref regex) => regex.is_match(value),
+        }
+    }
+}
+
+/// A condition on the value of a tag.
+#[derive(Clone, Debug)]
+pub struct TagFilter {
+    pub tag: String,
+    pub matcher: TagMatcher,
+}
+
+impl TagFilter {
+    /// Parses a filter of the form `TAG=VALUE` for an exact match or `TAG~REGEX` for a regular
+    /// expression match e.g. `White=stockfish` or `TimeControl~^6\.84`.
+    pub fn parse(spec: &str) -> Result<TagFilter> {
+        let separator = spec.find(['=', '~'])
+            .ok_or_else(|| format!("Tag filter '{}' should be TAG=VALUE or TAG~REGEX", spec))?;
+        let tag = spec[..separator].trim();
+        if tag.is_empty() {
+            return Err(From::from(format!("Tag filter '{}' has no tag name", spec)));
+        }
+
+        let value = &spec[separator + 1..];
+        let matcher = if spec[separator..].starts_with('=') {
+            TagMatcher::Exact(value.to_string())
+        } else {
+            TagMatcher::Regex(Regex::new(value).map_err(|err| {
+                format!("Tag filter '{}' has a bad regular expression: {}", spec, err)
+            })?)
+        };
+
+        Ok(TagFilter {
+            tag: tag.to_string(),
+            matcher,
+        })
+    }
+
+    // Whether the game has the tag with a matching value
+    fn is_match(&self, game: &Game) -> bool {
+        game.tags
+            .iter()
+            .any(|&(ref name, ref value)| *name == self.tag && self.matcher.is_match(value))
+    }
+}
+
+/// Selects the games to analyse by their tags.
+///
+/// A game is selected if it matches at least one include filter for each tag that has include
+/// filters, matches none of the exclude filters and has a Date within the date range. Games
+/// without a tag never match a filter on it and games with an unknown date are left out when a
+/// date range is given.
+#[derive(Clone, Debug, Default)]
+pub struct GameFilter {
+    pub include: Vec<TagFilter>,
+    pub exclude: Vec<TagFilter>,
+    /// The earliest date in the form `YYYY.MM.DD`.
+    pub date_from: Option<String>,
+    /// The latest date in the form `YYYY.MM.DD`.
+    pub date_to: Option<String>,
+}
+
+impl GameFilter {
+    /// Whether the filter selects every game.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty() && self.date_from.is_none()
+            && self.date_to.is_none()
+    }
+
+    pub fn matches(&self, game: &Game) -> bool {
+        let included = self.include.iter().all(|filter| {
+            filter.is_match(game)
+                || self.include
+                    .iter()
+                    .any(|other| other.tag == filter.tag && other.is_match(game))
+        });
+        if !included || self.exclude.iter().any(|filter| filter.is_match(game)) {
+            return false;
+        }
+
+        if self.date_from.is_none() && self.date_to.is_none() {
+            return true;
+        }
+        let date = game.tags
+            .iter()
+            .find(|&&(ref name, _)| name == "Date")
+            .and_then(|&(_, ref value)| parse_date(value).ok());
+        match date {
+            Some(date) => {
+                self.date_from.as_ref().is_none_or(|from| date >= *from)
+                    && self.date_to.as_ref().is_none_or(|to| date <= *to)
+            }
+            None => false,
+        }
+    }
+}
+
+/// Parses a date in the PGN form `YYYY.MM.DD` or the form `YYYY-MM-DD` and returns it in the
+/// PGN form. Dates with unknown parts e.g. `2017.??.??` are rejected.
+pub fn parse_date(date: &str) -> Result<String> {
+    let normalized = date.trim().replace('-', ".");
+    let parts: Vec<&str> = normalized.split('.').collect();
+    let is_valid = parts.len() == 3 && parts[0].len() == 4 && parts[1].len() == 2
+        && parts[2].len() == 2
+        && parts
+            .iter()
+            .all(|part| part.chars().all(|char| char.is_ascii_digit()));
+    if is_valid {
+        Ok(normalized)
+    } else {
+        Err(From::from(format!(
+            "Date '{}' should be YYYY.MM.DD or YYYY-MM-DD",
+            date
+        )))
+    }
+}
@@ -0,0 +1,381 @@
+use std::cmp;
+use std::collections::BTreeMap;
+use std::mem;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use special::Gamma;
+
+use stats::{fit_polya, polya_log_probability, FitOptions, InitialAlpha, PolyaFit};
+
+// Cells are pooled until their expected number of openings is at least this
+const MIN_EXPECTED: f64 = 5.;
+
+// Openings with more games than this have their counts of results pooled with those of similar
+// proportions before the cells are formed, so the number of cells doesn't grow with the square
+// of the number of games
+const MAX_EXACT_GAMES: u32 = 20;
+
+// The random number streams of the simulated books start after those of the bootstrap, which
+// has one for each of up to 2^32 replicates, so the two don't share random numbers
+const STREAM_OFFSET: u64 = 1 << 32;
+
+/// The options of [`goodness_of_fit`](fn.goodness_of_fit.html).
+#[derive(Clone, Copy, Debug)]
+pub struct GoodnessOfFitOptions {
+    /// The number of books simulated from the fit for the parametric bootstrap p-value. No
+    /// p-value is found with 0.
+    pub replicates: u32,
+    /// The seed of the random number generator. The same seed gives the same results whatever
+    /// the number of threads.
+    pub seed: u64,
+}
+
+impl Default for GoodnessOfFitOptions {
+    fn default() -> GoodnessOfFitOptions {
+        GoodnessOfFitOptions {
+            replicates: 1000,
+            seed: 0,
+        }
+    }
+}
+
+/// A cell of the goodness of fit table: the openings with one of a set of counts of results.
+#[derive(Clone, Debug, Default)]
+pub struct GoodnessOfFitCell {
+    /// The smallest and largest numbers of games of the openings in the cell.
+    pub games: (u32, u32),
+    /// The number of counts of results pooled into the cell.
+    pub results: usize,
+    /// The counts of results when the cell has only one, in the order of
+    /// [`fit_polya`](../stats/fn.fit_polya.html).
+    pub counts: Option<[u32; 3]>,
+    /// The number of openings with these results.
+    pub observed: u32,
+    /// The expected number of openings with these results under the fit.
+    pub expected: f64,
+}
+
+impl GoodnessOfFitCell {
+    /// The contribution of the cell to the chi-square statistic.
+    pub fn chi_square(&self) -> f64 {
+        let difference = f64::from(self.observed) - self.expected;
+        difference * difference / self.expected
+    }
+
+    // Pools the counts of results of `other` into the cell
+    fn add(&mut self, other: &GoodnessOfFitCell) {
+        if other.results == 0 {
+            return;
+        }
+        if self.results == 0 {
+            *self = other.clone();
+            return;
+        }
+        self.games = (
+            cmp::min(self.games.0, other.games.0),
+            cmp::max(self.games.1, other.games.1),
+        );
+        self.results += other.results;
+        self.counts = None;
+        self.observed += other.observed;
+        self.expected += other.expected;
+    }
+}
+
+/// The results of [`goodness_of_fit`](fn.goodness_of_fit.html).
+#[derive(Clone, Debug)]
+pub struct GoodnessOfFit {
+    pub cells: Vec<GoodnessOfFitCell>,
+    /// Pearson's chi-square statistic over the cells.
+    pub chi_square: f64,
+    /// The number of constraints on the totals of the cells, one for each number of games with
+    /// its own cells and one for the cell of the openings with the remaining numbers of games.
+    pub constraints: usize,
+    /// The degrees of freedom of the chi-square distribution the statistic is compared with,
+    /// allowing for the constraints and the three fitted parameters. There are none when there
+    /// are no more cells than those.
+    pub degrees_of_freedom: Option<u32>,
+    /// The p-value from the chi-square distribution, if there are any degrees of freedom.
+    pub p_value: Option<f64>,
+    /// The proportion of books simulated from the fit with a chi-square statistic at least as
+    /// large, if there were replicates. Books whose fit failed count as at least as large, so
+    /// with failures this is an upper bound.
+    pub bootstrap_p_value: Option<f64>,
+    pub replicates: u32,
+    /// The number of simulated books whose fit didn't converge.
+    pub failed_replicates: u32,
+}
+
+/// Tests how well the fitted Dirichlet-multinomial distribution describes the openings by
+/// comparing the number of openings with each count of results with the number expected under
+/// the fit.
+///
+/// Openings are compared with the expected counts of openings with the same number of games.
+/// The counts with few expected openings are pooled so each cell expects at least 5 openings,
+/// which can leave a last cell of openings with uncommon numbers of games expecting fewer. Above
+/// 20 games the counts are first pooled with those of similar proportions, in 21 ranges of
+/// white wins and of draws, so large openings don't need a cell for every count. The
+/// chi-square p-value is approximate, especially with few openings, and the parametric
+/// bootstrap p-value refits books simulated from the fit with the same numbers of games to
+/// find the distribution of the statistic instead.
+///
+/// The samples are in the order of `fit_polya` and `fit` is their fit. The simulated books are
+/// fitted with `fit_options` starting from the alpha of `fit`.
+pub fn goodness_of_fit(
+    samples: &[[u32; 3]],
+    fit: &PolyaFit,
+    fit_options: &FitOptions,
+    options: &GoodnessOfFitOptions,
+) -> GoodnessOfFit {
+    let (cells, constraints) = pooled_cells(samples, &fit.alpha);
+    let chi_square = cells.iter().map(GoodnessOfFitCell::chi_square).sum::<f64>();
+    let degrees_of_freedom = if cells.len() > constraints + 3 {
+        Some((cells.len() - constraints - 3) as u32)
+    } else {
+        None
+    };
+    let p_value = degrees_of_freedom.map(|degrees_of_freedom| {
+        1. - (chi_square / 2.).inc_gamma(f64::from(degrees_of_freedom) / 2.)
+    });
+
+    let fit_options = FitOptions {
+        initial_alpha: InitialAlpha::Value(fit.alpha),
+        ..*fit_options
+    };
+    let replicates: Vec<Option<f64>> = (0..options.replicates)
+        .into_par_iter()
+        .map(|replicate| {
+            let mut rng = ChaCha8Rng::seed_from_u64(options.seed);
+            rng.set_stream(STREAM_OFFSET + u64::from(replicate));
+            let simulated: Vec<[u32; 3]> = samples
+                .iter()
+                .map(|sample| polya_sample(&fit.alpha, sample.iter().sum(), &mut rng))
+                .collect();
+            let fit = fit_polya(&simulated, &fit_options);
+            if fit.converged {
+                let (cells, _) = pooled_cells(&simulated, &fit.alpha);
+                Some(cells.iter().map(GoodnessOfFitCell::chi_square).sum())
+            } else {
+                None
+            }
+        })
+        .collect();
+    // Leaving out the books whose fit failed could make the p-value too small
+    let larger = replicates
+        .iter()
+        .filter(|value| match **value {
+            Some(value) => value >= chi_square,
+            None => true,
+        })
+        .count();
+    let bootstrap_p_value = if replicates.is_empty() {
+        None
+    } else {
+        Some((larger + 1) as f64 / (replicates.len() + 1) as f64)
+    };
+
+    GoodnessOfFit {
+        cells,
+        chi_square,
+        constraints,
+        degrees_of_freedom,
+        p_value,
+        bootstrap_p_value,
+        replicates: options.replicates,
+        failed_replicates: replicates.iter().filter(|value| value.is_none()).count() as u32,
+    }
+}
+
+// The pooled cells of the openings and the number of constraints on their totals, which is one
+// for each number of games with its own cells and one for the last cell if openings with
+// different numbers of games are pooled into it
+fn pooled_cells(samples: &[[u32; 3]], alpha: &[f64; 3]) -> (Vec<GoodnessOfFitCell>, usize) {
+    let mut observed: BTreeMap<u32, BTreeMap<[u32; 3], u32>> = BTreeMap::new();
+    for sample in samples {
+        let total = sample.iter().sum::<u32>();
+        if total > 0 {
+            *observed
+                .entry(total)
+                .or_default()
+                .entry(*sample)
+                .or_insert(0) += 1;
+        }
+    }
+
+    let mut cells = Vec::new();
+    let mut constraints = 0;
+    let mut remainder = GoodnessOfFitCell::default();
+    for (&total, observed) in &observed {
+        let mut units: Vec<([u32; 2], GoodnessOfFitCell)> =
+            result_units(total, alpha, observed).into_iter().collect();
+        units.sort_by(|left, right| {
+            left.1
+                .expected
+                .partial_cmp(&right.1.expected)
+                .expect("Expected counts aren't NaN")
+                .then_with(|| left.0.cmp(&right.0))
+        });
+
+        let mut group: Vec<GoodnessOfFitCell> = Vec::new();
+        let mut cell = GoodnessOfFitCell::default();
+        for (_, unit) in units {
+            cell.add(&unit);
+            if cell.expected >= MIN_EXPECTED {
+                group.push(mem::take(&mut cell));
+            }
+        }
+
+        if group.is_empty() {
+            // Too few openings have this number of games for a cell of their own
+            remainder.add(&cell);
+        } else {
+            group.last_mut().expect("The group isn't empty").add(&cell);
+            cells.extend(group);
+            constraints += 1;
+        }
+    }
+    if remainder.results > 0 {
+        cells.push(remainder);
+        constraints += 1;
+    }
+    (cells, constraints)
+}
+
+// The observed and expected openings with `total` games for each count of results, with the
+// counts of more than MAX_EXACT_GAMES games pooled by their proportions
+fn result_units(
+    total: u32,
+    alpha: &[f64; 3],
+    observed: &BTreeMap<[u32; 3], u32>,
+) -> BTreeMap<[u32; 2], GoodnessOfFitCell> {
+    let openings = f64::from(observed.values().sum::<u32>());
+    let mut units: BTreeMap<[u32; 2], GoodnessOfFitCell> = BTreeMap::new();
+    for white in 0..total + 1 {
+        // Each probability follows from the one with a draw fewer, which is cheaper than finding
+        // it from the log-gamma functions
+        let mut log_probability = polya_log_probability(&[white, 0, total - white], alpha);
+        for draw in 0..total - white + 1 {
+            let black = total - white - draw;
+            if draw > 0 {
+                let previous_black = f64::from(black + 1);
+                log_probability += (previous_black * (f64::from(draw - 1) + alpha[1])
+                    / (f64::from(draw) * (f64::from(black) + alpha[2])))
+                    .ln();
+            }
+            let counts = [white, draw, black];
+            units.entry(unit_key(&counts, total)).or_default().add(&GoodnessOfFitCell {
+                games: (total, total),
+                results: 1,
+                counts: Some(counts),
+                observed: observed.get(&counts).cloned().unwrap_or(0),
+                expected: openings * log_probability.exp(),
+            });
+        }
+    }
+    units
+}
+
+// The white wins and draws of the counts, or their ranges of proportions when there are more
+// than MAX_EXACT_GAMES games
+fn unit_key(counts: &[u32; 3], total: u32) -> [u32; 2] {
+    if total <= MAX_EXACT_GAMES {
+        [counts[0], counts[1]]
+    } else {
+        let range = |count: u32| {
+            (u64::from(count) * u64::from(MAX_EXACT_GAMES + 1) / u64::from(total + 1)) as u32
+        };
+        [range(counts[0]), range(counts[1])]
+    }
+}
+
+// Draws the results of the games of an opening from a Polya urn, which gives a sample of the
+// Dirichlet-multinomial distribution with parameters `alpha`
+fn polya_sample<R: Rng>(alpha: &[f64; 3], games: u32, rng: &mut R) -> [u32; 3] {
+    let alpha_sum = alpha.iter().sum::<f64>();
+    let mut counts = [0; 3];
+    for game in 0..games {
+        let mut draw = rng.gen::<f64>() * (alpha_sum + f64::from(game));
+        let mut result = 0;
+        while result < 2 && draw >= alpha[result] + f64::from(counts[result]) {
+            draw -= alpha[result] + f64::from(counts[result]);
+            result += 1;
+        }
+        counts[result] += 1;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(replicates: u32) -> GoodnessOfFitOptions {
+        GoodnessOfFitOptions {
+            replicates,
+            ..GoodnessOfFitOptions::default()
+        }
+    }
+
+    #[test]
+    fn no_p_value_without_degrees_of_freedom() {
+        let samples = [[1, 1, 0], [0, 2, 0], [1, 0, 1], [2, 0, 0], [0, 1, 1], [1, 1, 0]];
+        let goodness_of_fit = goodness_of_fit(
+            &samples,
+            &PolyaFit::with_alpha([1.; 3]),
+            &FitOptions::default(),
+            &options(0),
+        );
+        assert_eq!(goodness_of_fit.cells.len(), 1);
+        assert_eq!(goodness_of_fit.constraints, 1);
+        assert_eq!(goodness_of_fit.degrees_of_freedom, None);
+        assert_eq!(goodness_of_fit.p_value, None);
+        assert_eq!(goodness_of_fit.bootstrap_p_value, None);
+    }
+
+    #[test]
+    fn cells_pool_results_with_few_expected_openings() {
+        let mut samples = vec![[3, 4, 3]; 200];
+        samples.extend(vec![[1, 1, 1]; 3]);
+        samples.push([0, 5, 0]);
+        let (cells, constraints) = pooled_cells(&samples, &[2., 2., 2.]);
+        assert_eq!(constraints, 2);
+        let observed = cells.iter().map(|cell| cell.observed).sum::<u32>();
+        assert_eq!(observed, 204);
+        // The openings with 3 and 5 games are too few for cells of their own
+        let remainder = cells.last().expect("There are cells");
+        assert_eq!(remainder.games, (3, 5));
+        assert_eq!(remainder.observed, 4);
+        assert_eq!(remainder.results, 10 + 21);
+        assert_eq!(remainder.counts, None);
+    }
+
+    #[test]
+    fn cells_of_large_openings_pool_similar_proportions() {
+        let mut samples = vec![[100, 60, 40]; 30];
+        samples.extend(vec![[20, 150, 30]; 30]);
+        let (cells, constraints) = pooled_cells(&samples, &[2., 3., 1.]);
+        assert_eq!(constraints, 1);
+        // Every count of 200 games is in a cell and the expected openings add up
+        let results = cells.iter().map(|cell| cell.results).sum::<usize>();
+        assert_eq!(results, 201 * 202 / 2);
+        let expected = cells.iter().map(|cell| cell.expected).sum::<f64>();
+        assert!((expected - 60.).abs() < 1e-9);
+        assert!(cells.len() <= 12);
+    }
+
+    #[test]
+    fn failed_books_count_as_larger() {
+        // Every simulated book has only draws so its fit fails
+        let samples = vec![[0, 3, 0]; 40];
+        let goodness_of_fit = goodness_of_fit(
+            &samples,
+            &PolyaFit::with_alpha([1e-9, 1., 1e-9]),
+            &FitOptions::default(),
+            &options(9),
+        );
+        assert_eq!(goodness_of_fit.failed_replicates, 9);
+        assert_eq!(goodness_of_fit.bootstrap_p_value, Some(1.));
+    }
+}
@@ -0,0 +1,132 @@
+use std::fs::{read_dir, File};
+use std::io::{stdin, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+use glob::glob;
+use xz2::bufread::XzDecoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+use Result;
+
+/// The input name that stands for stdin.
+pub const STDIN: &str = "-";
+
+/// The extensions of the files read when a directory is given as an input.
+/// Compressed files are recognised by an additional extension e.g. `games.pgn.gz`.
+pub const PGN_EXTENSIONS: &[&str] = &["pgn"];
+pub const COMPRESSED_EXTENSIONS: &[&str] = &["gz", "zst", "bz2", "xz"];
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// Expands the inputs given on the command line into a list of files.
+///
+/// Each input can be a file, a directory, a glob pattern or `-` for stdin. Directories are
+/// expanded to the PGN files directly inside them. The files from each input are sorted by path
+/// and files named more than once are only included the first time.
+pub fn expand_inputs<S: AsRef<str>>(inputs: &[S]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let input = input.as_ref();
+        let path = Path::new(input);
+
+        let mut expanded = if input == STDIN {
+            vec![path.to_path_buf()]
+        } else if path.is_dir() {
+            let mut dir_files = Vec::new();
+            for entry in read_dir(path)? {
+                let entry_path = entry?.path();
+                if entry_path.is_file() && is_pgn_file(&entry_path) {
+                    dir_files.push(entry_path);
+                }
+            }
+            dir_files
+        } else if is_glob_pattern(input) {
+            let mut matches = Vec::new();
+            for entry in glob(input)? {
+                let entry_path = entry?;
+                if entry_path.is_file() {
+                    matches.push(entry_path);
+                }
+            }
+            if matches.is_empty() {
+                return Err(From::from(format!("No files match '{}'", input)));
+            }
+            matches
+        } else {
+            vec![path.to_path_buf()]
+        };
+
+        expanded.sort();
+        for file in expanded {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Opens an input returned by [`expand_inputs`](fn.expand_inputs.html) for reading.
+///
+/// Input compressed with gzip, zstd, bzip2 or xz is detected from its first bytes and
+/// decompressed while it's read.
+pub fn open_input(path: &Path) -> Result<Box<dyn BufRead>> {
+    if path == Path::new(STDIN) {
+        decompress(BufReader::new(stdin()))
+    } else {
+        decompress(BufReader::new(File::open(path)?))
+    }
+}
+
+/// Wraps `reader` in a decoder if its contents are compressed.
+pub fn decompress<R: BufRead + 'static>(mut reader: R) -> Result<Box<dyn BufRead>> {
+    let decoded: Box<dyn BufRead> = {
+        let header = reader.fill_buf()?;
+        if header.starts_with(GZIP_MAGIC) {
+            Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+        } else if header.starts_with(ZSTD_MAGIC) {
+            Box::new(BufReader::new(ZstdDecoder::with_buffer(reader)?))
+        } else if header.starts_with(BZIP2_MAGIC) {
+            Box::new(BufReader::new(MultiBzDecoder::new(reader)))
+        } else if header.starts_with(XZ_MAGIC) {
+            Box::new(BufReader::new(XzDecoder::new_multi_decoder(reader)))
+        } else {
+            Box::new(reader)
+        }
+    };
+    Ok(decoded)
+}
+
+fn is_pgn_file(path: &Path) -> bool {
+    let file_name = match path.file_name().and_then(|file_name| file_name.to_str()) {
+        Some(file_name) => file_name.to_ascii_lowercase(),
+        None => return false,
+    };
+
+    let uncompressed_name = COMPRESSED_EXTENSIONS
+        .iter()
+        .filter_map(|extension| {
+            let suffix = format!(".{}", extension);
+            if file_name.ends_with(&suffix) {
+                Some(&file_name[..file_name.len() - suffix.len()])
+            } else {
+                None
+            }
+        })
+        .next()
+        .unwrap_or(&file_name);
+
+    PGN_EXTENSIONS
+        .iter()
+        .any(|extension| uncompressed_name.ends_with(&format!(".{}", extension)))
+}
+
+fn is_glob_pattern(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
@@ -0,0 +1,737 @@
+//! Analysis of computer chess opening books via the results of engine self-play.
+//!
+//! The usual flow is to read the games of a PGN file with
+//! [`pgn::GameReader`](pgn/struct.GameReader.html), group them by opening with an
+//! [`OpeningAggregator`](struct.OpeningAggregator.html), fit a Dirichlet-multinomial distribution
+//! to the per-opening results with [`stats::fit_polya`](stats/fn.fit_polya.html) and then write
+//! the tables and plots with the functions in [`output`](output/index.html) and
+//! [`plot`](plot/index.html). [`run`](fn.run.html) does all of this in one go.
+
+extern crate arrow_array;
+extern crate arrow_ipc;
+extern crate arrow_schema;
+extern crate bzip2;
+extern crate chess_pgn_parser;
+extern crate flate2;
+extern crate glob;
+extern crate rand;
+extern crate rand_chacha;
+extern crate rayon;
+extern crate regex;
+#[macro_use]
+extern crate rusqlite;
+#[macro_use]
+extern crate serde_json;
+extern crate special;
+extern crate xz2;
+extern crate zstd;
+
+pub mod arrow;
+pub mod board;
+pub mod bootstrap;
+pub mod database;
+mod error;
+pub mod filter;
+pub mod goodness_of_fit;
+pub mod input;
+pub mod matchup;
+mod opening;
+pub mod output;
+pub mod pairs;
+pub mod pgn;
+pub mod plot;
+pub mod quarantine;
+pub mod report;
+pub mod stats;
+
+pub use error::{Error, ErrorKind};
+pub use opening::{aggregate_openings, count_wdb, total_result, AggregationOptions, OpeningAggregator,
+                  OpeningKey, OpeningResult, Perspective, UnfinishedPolicy};
+
+use arrow::{print_openings_arrow, GameTableWriter};
+use bootstrap::{bootstrap, BootstrapOptions, MAX_FAILED_SHARE};
+use database::Database;
+use filter::GameFilter;
+use goodness_of_fit::{goodness_of_fit, GoodnessOfFitOptions};
+use input::{expand_inputs, open_input};
+use matchup::{directory_name, MatchupGrouping, MatchupSummary};
+use output::{print_bootstrap, print_detailed_opening_stats, print_goodness_of_fit,
+             print_matchup_json, print_matchup_summary, print_opening_stats, print_openings_json,
+             print_pair_stats, print_pentanomials, print_player_opening_stats,
+             print_skipped_games, print_summary_json, print_wdb_counts, sort_openings,
+             sort_wdb_counts, SortBy, StatsFormat, Summary};
+use pairs::PairAggregator;
+use pgn::GameReader;
+use plot::{print_dirichlet_plot_svg, print_scatter_plot_svg};
+use quarantine::Quarantine;
+use report::{print_html_report, print_markdown_report};
+use stats::{alpha_uncertainty, fit_polya, FitOptions, PolyaFit, Z_95};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{create_dir, create_dir_all, read_dir, remove_dir_all, remove_file, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// The file the games rejected in lenient mode are written to
+const REJECTED_FILE: &str = "rejected.pgn";
+
+/// The options of an analysis.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// The games to analyse. Other games are ignored.
+    pub filter: GameFilter,
+    pub aggregation: AggregationOptions,
+    /// Whether games that can't be analysed are written to `rejected.pgn` instead of stopping
+    /// the analysis.
+    pub lenient: bool,
+    /// How games are grouped into matchups that are also analysed separately, if at all.
+    pub matchups: Option<MatchupGrouping>,
+    /// Whether games of the same opening between the same players with colours reversed are
+    /// paired up and the points of the pairs counted.
+    pub pairs: bool,
+    /// Whether the results of each opening are also written to `openings.json`.
+    pub openings_json: bool,
+    /// The columns of `opening_stats.csv`.
+    pub stats_format: StatsFormat,
+    /// The order of the rows of `opening_stats.csv`, `wdb_counts.csv` and `openings.json`.
+    pub sort_by: SortBy,
+    /// An SQLite database the games, openings and fits are added to as a new run.
+    pub database: Option<PathBuf>,
+    /// Whether the games and the results of each opening are also written to `games.arrow`
+    /// and `openings.arrow`.
+    pub arrow: bool,
+    /// The options of the Dirichlet fit.
+    pub fit: FitOptions,
+    /// The options of the bootstrap of the fit, if there is one.
+    pub bootstrap: Option<BootstrapOptions>,
+    /// The options of the goodness of fit test, if there is one.
+    pub goodness_of_fit: Option<GoodnessOfFitOptions>,
+}
+
+/// Analyses the games in the PGN files `inputs` and writes the results to the directory `output`.
+///
+/// The inputs are expanded with [`input::expand_inputs`](input/fn.expand_inputs.html) and the
+/// games of all the files are analysed together. The output directory must not already exist. It
+/// is created before any input is read and if the analysis fails everything written to it is
+/// removed again, except the rejected games in lenient mode.
+///
+/// When `options.matchups` is set the games of each matchup are also analysed on their own and
+/// the results written to a subdirectory of `output/matchups` with a summary of all the matchups
+/// in `matchups.csv`.
+///
+/// When `options.pairs` is set the points of colour-reversed pairs of games are written to
+/// `pairs.csv` for each opening and `pentanomial.csv` for each pair of players.
+///
+/// The totals and the fit are also written to `summary.json` and, with the plots and the results
+/// of each opening, to `report.html` and as Markdown to `report.md`. A fit that doesn't converge
+/// is a warning rather than an error and everything that needs the fitted alpha is left out.
+///
+/// When `options.database` is set the games, openings and fits are also added to the database
+/// as a new run. Only the games counted against an opening are added, as for `options.arrow`.
+/// See [`database::Database`](database/struct.Database.html).
+///
+/// When `options.arrow` is set the games and openings are also written as Arrow IPC files.
+///
+/// When `options.bootstrap` is set the openings are resampled to find confidence intervals for
+/// the fit and other statistics of the book, which are written to `bootstrap.csv`. See
+/// [`bootstrap::bootstrap`](bootstrap/fn.bootstrap.html).
+///
+/// When `options.goodness_of_fit` is set the number of openings with each count of results is
+/// compared with the number expected under the fit and written to `goodness_of_fit.csv`. See
+/// [`goodness_of_fit::goodness_of_fit`](goodness_of_fit/fn.goodness_of_fit.html).
+pub fn run<S: AsRef<str>>(inputs: &[S], output: &str, options: &Options) -> Result<()> {
+    let files = expand_inputs(inputs)?;
+
+    let output_path = Path::new(output);
+    create_dir(output_path)?;
+    analyse(files, output, options).inspect_err(|_| remove_partial_output(output_path))
+}
+
+// Removes the tables and plots of a failed analysis, which could be mistaken for its results,
+// but keeps the rejected games so they can be inspected
+fn remove_partial_output(output_path: &Path) {
+    let entries = match read_dir(output_path) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if entry.file_name() == REJECTED_FILE {
+            continue;
+        }
+        let _ = if path.is_dir() {
+            remove_dir_all(&path)
+        } else {
+            remove_file(&path)
+        };
+    }
+}
+
+// Analyses the games of the input files and writes the results to the output directory, which
+// has already been created
+fn analyse(files: Vec<PathBuf>, output: &str, options: &Options) -> Result<()> {
+    let output_path = Path::new(output);
+    let mut quarantine = if options.lenient {
+        Some(Quarantine::new(BufWriter::new(File::create(
+            output_path.join(REJECTED_FILE),
+        )?)))
+    } else {
+        None
+    };
+
+    let mut database = match options.database {
+        Some(ref path) => Some(Database::open(path, output, options)?),
+        None => None,
+    };
+
+    let mut game_table = if options.arrow {
+        Some(GameTableWriter::new(BufWriter::new(File::create(
+            output_path.join("games.arrow"),
+        )?))?)
+    } else {
+        None
+    };
+
+    let mut aggregator = OpeningAggregator::with_options(options.aggregation.clone());
+    let mut matchup_aggregators = BTreeMap::new();
+    let mut pair_aggregator = if options.pairs {
+        Some(PairAggregator::with_options(options.aggregation.clone()))
+    } else {
+        None
+    };
+    let mut file_game_counts = Vec::new();
+    let mut filtered_game_count = 0;
+    for path in files {
+        let file = open_input(&path).map_err(|err| err.in_file(&path))?;
+        let games_before = aggregator.total_games();
+        let mut reader = GameReader::new(file);
+        while let Some(game) = reader.next() {
+            let result = game
+                .and_then(|game| {
+                    if options.filter.matches(&game) {
+                        let opening = aggregator.add_game(&game)?;
+                        if let Some(grouping) = options.matchups {
+                            matchup_aggregators
+                                .entry(grouping.matchup(&game))
+                                .or_insert_with(|| {
+                                    OpeningAggregator::with_options(options.aggregation.clone())
+                                })
+                                .add_game(&game)?;
+                        }
+                        if let Some(ref mut pair_aggregator) = pair_aggregator {
+                            pair_aggregator.add_game(&game)?;
+                        }
+                        // Only the games counted against an opening are written so the games
+                        // agree with the openings
+                        if let Some(opening) = opening {
+                            if let Some(ref mut database) = database {
+                                database.add_game(&path, reader.position(), &game, &opening)?;
+                            }
+                            if let Some(ref mut game_table) = game_table {
+                                game_table.add_game(&path, reader.position(), &game, &opening)?;
+                            }
+                        }
+                        Ok(())
+                    } else {
+                        filtered_game_count += 1;
+                        Ok(())
+                    }
+                })
+                .map_err(|err| err.at(reader.position()).in_file(&path));
+            if let Err(err) = result {
+                match quarantine {
+                    Some(ref mut quarantine) if err.kind().is_game_error() => {
+                        quarantine.reject(&err, reader.text())?
+                    }
+                    _ => return Err(err),
+                }
+            }
+        }
+        let game_count = aggregator.total_games() - games_before;
+        if let Some(ref mut database) = database {
+            database.add_input_file(&path, game_count)?;
+        }
+        file_game_counts.push((path, game_count));
+    }
+
+    println!("Input files: {}", file_game_counts.len());
+    for &(ref path, game_count) in &file_game_counts {
+        println!("  {}: {} games", path.display(), game_count);
+    }
+    if !options.filter.is_empty() {
+        println!("Filtered out games: {}", filtered_game_count);
+    }
+    println!("Total games: {}", aggregator.total_games());
+    if aggregator.options().normalize_fen && aggregator.options().key.is_fen() {
+        println!("Merged FEN keys: {}", aggregator.merged_key_count());
+    }
+    if let Some(ref mut quarantine) = quarantine {
+        quarantine.flush()?;
+        println!("Rejected games: {}", quarantine.total());
+        for (reason, count) in quarantine.counts() {
+            println!("  {}: {}", reason, count);
+        }
+    }
+    if !aggregator.skipped_games().is_empty() {
+        println!("Skipped unfinished games: {}", aggregator.total_skipped_games());
+        print_skipped_games(
+            File::create(output_path.join("skipped_games.csv"))?,
+            aggregator.skipped_games(),
+            options.aggregation.key,
+        )?;
+    }
+
+    if aggregator.short_games() > 0 {
+        println!("Games too short for their opening: {}", aggregator.short_games());
+    }
+    if let Perspective::Player(ref player) = options.aggregation.perspective {
+        println!("Games not played by {}: {}", player, aggregator.other_games());
+    }
+
+    println!("Total openings: {}", aggregator.opening_stats().len());
+    // A failed fit still leaves the counts of the openings to report, so only the statistics
+    // that need alpha are left out
+    let fit = analyse_openings(output_path, &aggregator, options)?;
+    let alpha = fit.alpha;
+    let sort_by = fit_sort_by(options.sort_by, &fit);
+    if fit.converged {
+        println!(
+            "Fitted Dirichlet Alpha: ({:.3}, {:.3}, {:.3})",
+            alpha[0], alpha[1], alpha[2]
+        );
+        println!(
+            "Fit converged after {} iterations, log-likelihood {:.3}",
+            fit.iterations, fit.log_likelihood
+        );
+    } else {
+        eprintln!(
+            "Warning: the Dirichlet fit failed after {} iterations: {}",
+            fit.iterations,
+            fit.stop_reason.description()
+        );
+    }
+    let uncertainty = if fit.converged {
+        alpha_uncertainty(&samples(aggregator.opening_stats()), &alpha, Z_95)
+    } else {
+        None
+    };
+    match uncertainty {
+        Some(ref uncertainty) => {
+            let errors = &uncertainty.standard_errors;
+            let intervals = &uncertainty.intervals;
+            println!(
+                "Alpha Standard Errors: ({:.3}, {:.3}, {:.3})",
+                errors[0], errors[1], errors[2]
+            );
+            println!(
+                "Alpha 95% Confidence Intervals: ([{:.3}, {:.3}], [{:.3}, {:.3}], [{:.3}, {:.3}])",
+                intervals[0].0,
+                intervals[0].1,
+                intervals[1].0,
+                intervals[1].1,
+                intervals[2].0,
+                intervals[2].1
+            );
+            println!(
+                "Alpha Sum: {:.3}, Standard Error {:.3}, 95% Confidence Interval [{:.3}, {:.3}]",
+                alpha.iter().sum::<f64>(),
+                uncertainty.sum_standard_error,
+                uncertainty.sum_interval.0,
+                uncertainty.sum_interval.1
+            );
+            let mean_intervals = &uncertainty.mean_intervals;
+            println!(
+                "Mean 95% Confidence Intervals: ([{:.3}, {:.3}], [{:.3}, {:.3}], [{:.3}, {:.3}])",
+                mean_intervals[0].0,
+                mean_intervals[0].1,
+                mean_intervals[1].0,
+                mean_intervals[1].1,
+                mean_intervals[2].0,
+                mean_intervals[2].1
+            );
+        }
+        None if fit.converged => println!("The standard errors of alpha couldn't be estimated"),
+        None => {}
+    }
+    let bootstrap = match options.bootstrap {
+        Some(ref bootstrap_options) if fit.converged => {
+            let bootstrap = bootstrap(
+                &samples(aggregator.opening_stats()),
+                &fit,
+                &options.fit,
+                bootstrap_options,
+                &options.aggregation.perspective.columns(),
+            );
+            println!(
+                "Bootstrap Replicates: {} ({} with unbounded alpha, {} failed to converge)",
+                bootstrap.replicates, bootstrap.unbounded_replicates, bootstrap.failed_replicates
+            );
+            if bootstrap.too_many_failures() {
+                println!(
+                    "Bootstrap intervals withheld as more than {}% of the replicates failed",
+                    100. * MAX_FAILED_SHARE
+                );
+            }
+            print_bootstrap(
+                File::create(output_path.join("bootstrap.csv"))?,
+                &bootstrap,
+            )?;
+            Some(bootstrap)
+        }
+        _ => None,
+    };
+    let goodness_of_fit = match options.goodness_of_fit {
+        Some(ref goodness_of_fit_options) if fit.converged => {
+            let goodness_of_fit = goodness_of_fit(
+                &samples(aggregator.opening_stats()),
+                &fit,
+                &options.fit,
+                goodness_of_fit_options,
+            );
+            match goodness_of_fit.degrees_of_freedom {
+                Some(degrees_of_freedom) => println!(
+                    "Goodness of Fit: chi-square {:.3} with {} degrees of freedom over {} cells",
+                    goodness_of_fit.chi_square,
+                    degrees_of_freedom,
+                    goodness_of_fit.cells.len()
+                ),
+                None => println!(
+                    "Goodness of Fit: chi-square {:.3} over {} cells, too few for a p-value with \
+                     {} constraints on their totals and 3 fitted parameters",
+                    goodness_of_fit.chi_square,
+                    goodness_of_fit.cells.len(),
+                    goodness_of_fit.constraints
+                ),
+            }
+            if let Some(p_value) = goodness_of_fit.p_value {
+                println!("Goodness of Fit p-value: {:.4}", p_value);
+            }
+            if let Some(p_value) = goodness_of_fit.bootstrap_p_value {
+                println!(
+                    "Goodness of Fit Bootstrap p-value: {:.4} ({} replicates, {} failed to \
+                     converge and counted as at least as large)",
+                    p_value, goodness_of_fit.replicates, goodness_of_fit.failed_replicates
+                );
+            }
+            print_goodness_of_fit(
+                File::create(output_path.join("goodness_of_fit.csv"))?,
+                &goodness_of_fit,
+            )?;
+            Some(goodness_of_fit)
+        }
+        _ => None,
+    };
+    if let Some(ref mut database) = database {
+        database.add_openings(None, aggregator.opening_stats())?;
+        if fit.converged {
+            database.add_fit(None, aggregator.opening_stats(), &fit)?;
+        }
+    }
+
+    let summary = Summary {
+        input_files: file_game_counts,
+        total_games: aggregator.total_games(),
+        filtered_games: if options.filter.is_empty() {
+            None
+        } else {
+            Some(filtered_game_count)
+        },
+        skipped_games: aggregator.total_skipped_games(),
+        short_games: aggregator.short_games(),
+        rejected_games: quarantine
+            .as_ref()
+            .map(|quarantine| quarantine.counts().clone()),
+        merged_fen_keys: if aggregator.options().normalize_fen
+            && aggregator.options().key.is_fen()
+        {
+            Some(aggregator.merged_key_count())
+        } else {
+            None
+        },
+        result: total_result(aggregator.opening_stats()),
+        opening_count: aggregator.opening_stats().len(),
+        fit,
+        uncertainty,
+        bootstrap,
+        goodness_of_fit,
+        warnings: data_warnings(
+            &aggregator,
+            &fit,
+            quarantine.as_ref().map(Quarantine::counts),
+            pair_aggregator.as_ref().map(PairAggregator::unpaired_games),
+        ),
+    };
+    print_summary_json(
+        BufWriter::new(File::create(output_path.join("summary.json"))?),
+        &summary,
+        &options.aggregation.perspective,
+    )?;
+    print_html_report(
+        BufWriter::new(File::create(output_path.join("report.html"))?),
+        &summary,
+        &sort_openings(aggregator.opening_stats(), sort_by, &alpha),
+        options.aggregation.key,
+        &options.aggregation.perspective,
+    )?;
+    print_markdown_report(
+        BufWriter::new(File::create(output_path.join("report.md"))?),
+        &summary,
+        aggregator.opening_stats(),
+        options.aggregation.key,
+        &options.aggregation.perspective,
+    )?;
+    if let Some(game_table) = game_table {
+        game_table.finish()?;
+        print_openings_arrow(
+            BufWriter::new(File::create(output_path.join("openings.arrow"))?),
+            &sort_openings(aggregator.opening_stats(), sort_by, &alpha),
+            options.aggregation.key,
+            &options.aggregation.perspective.columns(),
+        )?;
+    }
+    if options.openings_json {
+        print_openings_json(
+            BufWriter::new(File::create(output_path.join("openings.json"))?),
+            &sort_openings(aggregator.opening_stats(), sort_by, &alpha),
+            options.aggregation.key,
+            &options.aggregation.perspective,
+        )?;
+    }
+
+    if let Some(pair_aggregator) = pair_aggregator {
+        print_pair_stats(
+            File::create(output_path.join("pairs.csv"))?,
+            pair_aggregator.pentanomials(),
+            options.aggregation.key,
+        )?;
+        let pentanomials = pair_aggregator.matchup_pentanomials();
+        print_pentanomials(
+            File::create(output_path.join("pentanomial.csv"))?,
+            &pentanomials,
+        )?;
+
+        println!("Unpaired games: {}", pair_aggregator.unpaired_games());
+        for (&(player, opponent), pentanomial) in &pentanomials {
+            let counts = &pentanomial.counts;
+            println!(
+                "Pentanomial {} vs {}: ({}, {}, {}, {}, {}), score {:.3}, variance {:.4}",
+                player,
+                opponent,
+                counts[0],
+                counts[1],
+                counts[2],
+                counts[3],
+                counts[4],
+                pentanomial.score(),
+                pentanomial.variance()
+            );
+        }
+    }
+
+    if options.matchups.is_some() {
+        // Matchups can be empty when only the games of a player are counted
+        let matchup_aggregators: Vec<_> = matchup_aggregators
+            .into_iter()
+            .filter(|&(_, ref aggregator)| aggregator.total_games() > 0)
+            .collect();
+        println!("Matchups: {}", matchup_aggregators.len());
+        let mut summaries = Vec::new();
+        let mut directories = HashSet::new();
+        for (matchup, aggregator) in matchup_aggregators {
+            let directory = directory_name(&matchup, &mut directories);
+            let matchup_path = output_path.join("matchups").join(&directory);
+            create_dir_all(&matchup_path)?;
+
+            // A matchup with too few games or openings for a fit doesn't stop the analysis
+            let fit = analyse_openings(&matchup_path, &aggregator, options)?;
+            let opening_stats = aggregator.into_opening_stats();
+            let result = total_result(&opening_stats);
+            if let Some(ref mut database) = database {
+                database.add_openings(Some(&matchup), &opening_stats)?;
+                if fit.converged {
+                    database.add_fit(Some(&matchup), &opening_stats, &fit)?;
+                }
+            }
+            let fitted = if fit.converged {
+                format!(
+                    "alpha ({:.3}, {:.3}, {:.3})",
+                    fit.alpha[0], fit.alpha[1], fit.alpha[2]
+                )
+            } else {
+                format!("fit failed ({})", fit.stop_reason.description())
+            };
+            println!(
+                "  {}: {} games, {} openings, {}",
+                matchup,
+                result.total_games(),
+                opening_stats.len(),
+                fitted
+            );
+            let summary = MatchupSummary {
+                matchup,
+                directory,
+                opening_count: opening_stats.len(),
+                result,
+                fit,
+            };
+            print_matchup_json(
+                File::create(matchup_path.join("summary.json"))?,
+                &summary,
+                &options.aggregation.perspective.columns(),
+            )?;
+            summaries.push(summary);
+        }
+        print_matchup_summary(
+            File::create(output_path.join("matchups.csv"))?,
+            &summaries,
+            &options.aggregation.perspective.columns(),
+        )?;
+    }
+
+    if let Some(database) = database {
+        println!("Database run id: {}", database.run_id());
+        database.finish()?;
+    }
+
+    Ok(())
+}
+
+// Describes the problems with the games that may affect the results
+fn data_warnings(
+    aggregator: &OpeningAggregator,
+    fit: &PolyaFit,
+    rejected_games: Option<&BTreeMap<&'static str, u32>>,
+    unpaired_games: Option<usize>,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if !fit.converged {
+        warnings.push(format!(
+            "The Dirichlet fit failed: {}",
+            fit.stop_reason.description()
+        ));
+    }
+    for (reason, count) in rejected_games.into_iter().flatten() {
+        warnings.push(format!("{} games were rejected: {}", count, reason));
+    }
+    if aggregator.total_skipped_games() > 0 {
+        warnings.push(format!(
+            "{} unfinished games were skipped",
+            aggregator.total_skipped_games()
+        ));
+    }
+    if aggregator.short_games() > 0 {
+        warnings.push(format!(
+            "{} games were skipped because they're shorter than the plies of the opening key",
+            aggregator.short_games()
+        ));
+    }
+    if aggregator.merged_key_count() > 0 {
+        warnings.push(format!(
+            "{} FEN keys were merged into another key by normalisation",
+            aggregator.merged_key_count()
+        ));
+    }
+    if let Perspective::Player(ref player) = aggregator.options().perspective {
+        if aggregator.other_games() > 0 {
+            warnings.push(format!(
+                "{} games weren't played by {}",
+                aggregator.other_games(),
+                player
+            ));
+        }
+    }
+    let single_game_openings = aggregator
+        .opening_stats()
+        .values()
+        .filter(|result| result.total_games() == 1)
+        .count();
+    if single_game_openings > 0 {
+        warnings.push(format!(
+            "{} openings have only one game",
+            single_game_openings
+        ));
+    }
+    if let Some(unpaired_games) = unpaired_games {
+        if unpaired_games > 0 {
+            warnings.push(format!("{} games couldn't be paired", unpaired_games));
+        }
+    }
+    warnings
+}
+
+// The counts of the results of each opening, sorted so the fit doesn't depend on the order of
+// the openings
+fn samples(opening_stats: &HashMap<String, OpeningResult>) -> Vec<[u32; 3]> {
+    sort_openings(opening_stats, SortBy::Key, &[0.; 3])
+        .into_iter()
+        .map(|(_, result)| result.counts())
+        .collect()
+}
+
+// The order of the openings, which can't be by their imbalance if the fit didn't converge
+fn fit_sort_by(sort_by: SortBy, fit: &PolyaFit) -> SortBy {
+    match sort_by {
+        SortBy::Imbalance if !fit.converged => SortBy::Total,
+        sort_by => sort_by,
+    }
+}
+
+// Writes the tables and plots of the openings to the directory `output_path` and returns the
+// Dirichlet fit. The fit may not have converged, in which case there's no contour plot and the
+// openings aren't sorted by their imbalance.
+fn analyse_openings(
+    output_path: &Path,
+    aggregator: &OpeningAggregator,
+    options: &Options,
+) -> Result<PolyaFit> {
+    let aggregation = aggregator.options();
+    let opening_stats = aggregator.opening_stats();
+
+    let fit = fit_polya(&samples(opening_stats), &options.fit);
+    let alpha = fit.alpha;
+    let sort_by = fit_sort_by(options.sort_by, &fit);
+
+    let openings = sort_openings(opening_stats, sort_by, &alpha);
+    let stats_file = File::create(output_path.join("opening_stats.csv"))?;
+    match (options.stats_format, &aggregation.perspective) {
+        (StatsFormat::Detailed, perspective) => print_detailed_opening_stats(
+            stats_file,
+            &openings,
+            aggregation.key,
+            &perspective.columns(),
+        )?,
+        (StatsFormat::Proportions, &Perspective::Colour) => {
+            print_opening_stats(stats_file, &openings, aggregation.key)?
+        }
+        (StatsFormat::Proportions, &Perspective::Player(_)) => print_player_opening_stats(
+            stats_file,
+            &openings,
+            aggregator.white_stats(),
+            aggregation.key,
+        )?,
+    }
+
+    let wdb_counts = count_wdb(opening_stats);
+    let labels = aggregation.perspective.labels();
+
+    print_wdb_counts(
+        File::create(output_path.join("wdb_counts.csv"))?,
+        &sort_wdb_counts(&wdb_counts, sort_by, &alpha),
+    )?;
+    print_scatter_plot_svg(
+        File::create(output_path.join("scatter_plot.svg"))?,
+        &wdb_counts,
+        &labels,
+    )?;
+    if fit.converged {
+        print_dirichlet_plot_svg(
+            File::create(output_path.join("dirichlet_contour_plot.svg"))?,
+            &alpha,
+            &labels,
+        )?;
+    }
+
+    Ok(fit)
+}
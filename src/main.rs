@@ -1,27 +1,19 @@
-extern crate chess_pgn_parser;
+extern crate cetoba;
 #[macro_use]
 extern crate clap;
-extern crate special;
 
-mod plot;
-mod stats;
+use cetoba::bootstrap::BootstrapOptions;
+use cetoba::filter::{parse_date, GameFilter, TagFilter};
+use cetoba::goodness_of_fit::GoodnessOfFitOptions;
+use cetoba::matchup::MatchupGrouping;
+use cetoba::output::{SortBy, StatsFormat};
+use cetoba::stats::{FitMethod, FitOptions, InitialAlpha};
+use cetoba::{AggregationOptions, OpeningKey, Options, Perspective, UnfinishedPolicy};
+use clap::{App, Arg, ArgMatches};
 
-use plot::{print_dirichlet_plot_svg, print_scatter_plot_svg};
-use stats::fit_polya;
-
-use chess_pgn_parser::GameTermination;
-use clap::{App, Arg};
-
-use std::collections::HashMap;
-use std::error::Error;
-use std::fs::{create_dir, File};
-use std::io::{Read, Write};
-use std::iter::Iterator;
-use std::path::Path;
+use std::path::PathBuf;
 use std::process::exit;
 
-type Result<T> = std::result::Result<T, Box<Error>>;
-
 fn main() {
     let matches = App::new("Chess Engine Test Opening Book Analyser")
         .version(crate_version!())
@@ -29,178 +21,361 @@ fn main() {
         .about("Analyzes opening books based on engine self-play")
         .arg(
             Arg::with_name("INPUT")
-                .help("A PGN file containing the engine self-play results")
-                .required(true),
+                .help(
+                    "PGN files containing the engine self-play results. \
+                     Directories and glob patterns are expanded to the PGN files they contain. \
+                     Compressed files are decompressed and - reads from stdin.",
+                )
+                .required(true)
+                .multiple(true),
         )
         .arg(
             Arg::with_name("OUTPUT")
                 .help("The path to output the analysis")
                 .required(true),
         )
+        .arg(
+            Arg::with_name("opening-key")
+                .long("opening-key")
+                .help(
+                    "How openings are identified: by the FEN tag, by the moves of the first \
+                     plies or by the position reached after the first plies",
+                )
+                .possible_values(&["fen", "moves", "position"])
+                .default_value("fen"),
+        )
+        .arg(
+            Arg::with_name("plies")
+                .long("plies")
+                .help("The number of plies in each opening when identifying openings by moves")
+                .default_value("16"),
+        )
+        .arg(Arg::with_name("raw-fen").long("raw-fen").help(
+            "Group openings by the FEN exactly as it appears instead of ignoring the move \
+             counters and unusable en passant squares",
+        ))
+        .arg(
+            Arg::with_name("unfinished")
+                .long("unfinished")
+                .help(
+                    "What to do with games that didn't end in a win, draw or loss: skip them, \
+                     stop with an error or count them as draws",
+                )
+                .possible_values(&["skip", "error", "draw"])
+                .default_value("error"),
+        )
+        .arg(Arg::with_name("lenient").long("lenient").help(
+            "Write games that can't be analysed to rejected.pgn in the output directory \
+             instead of stopping",
+        ))
+        .arg(
+            Arg::with_name("include")
+                .long("include")
+                .value_name("TAG=VALUE|TAG~REGEX")
+                .help(
+                    "Only analyse games with a tag equal to VALUE or matching REGEX e.g. \
+                     White=stockfish. Games must match one filter for each tag that is \
+                     filtered.",
+                )
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .long("exclude")
+                .value_name("TAG=VALUE|TAG~REGEX")
+                .help("Don't analyse games with a tag equal to VALUE or matching REGEX")
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("date-from")
+                .long("date-from")
+                .value_name("YYYY.MM.DD")
+                .help("Only analyse games played on or after this date"),
+        )
+        .arg(
+            Arg::with_name("date-to")
+                .long("date-to")
+                .value_name("YYYY.MM.DD")
+                .help("Only analyse games played on or before this date"),
+        )
+        .arg(
+            Arg::with_name("matchups")
+                .long("matchups")
+                .help(
+                    "Also analyse the games of each pair of players separately: ordered \
+                     keeps White and Black apart, unordered combines both colours",
+                )
+                .possible_values(&["ordered", "unordered"])
+                .takes_value(true),
+        )
+        .arg(Arg::with_name("pairs").long("pairs").help(
+            "Pair up games of the same opening between the same players with colours reversed \
+             and write the pentanomial counts of the pairs",
+        ))
+        .arg(
+            Arg::with_name("player")
+                .long("player")
+                .value_name("NAME")
+                .help(
+                    "Count the wins, draws and losses of the player NAME in the games they \
+                     played instead of the results by colour",
+                ),
+        )
+        .arg(Arg::with_name("openings-json").long("openings-json").help(
+            "Also write the results of each opening with its full key to openings.json",
+        ))
+        .arg(
+            Arg::with_name("stats-format")
+                .long("stats-format")
+                .help(
+                    "The columns of opening_stats.csv: the results as proportions or the full \
+                     key, the counts of the results and the score and draw rate with 95% \
+                     confidence intervals",
+                )
+                .possible_values(&["proportions", "detailed"])
+                .default_value("proportions"),
+        )
+        .arg(
+            Arg::with_name("sort-by")
+                .long("sort-by")
+                .help(
+                    "The order of the openings in opening_stats.csv and openings.json and of \
+                     the results in wdb_counts.csv: by FEN or other key, by the number of \
+                     games, draw rate, score or posterior imbalance, highest first, or by the \
+                     number of openings with each result",
+                )
+                .possible_values(&["fen", "total", "draw-rate", "score", "imbalance", "count"])
+                .default_value("fen"),
+        )
+        .arg(
+            Arg::with_name("sqlite")
+                .long("sqlite")
+                .value_name("DATABASE")
+                .help(
+                    "Also add the games, openings and fits to the SQLite database DATABASE as \
+                     a new run, creating it if it doesn't exist",
+                ),
+        )
+        .arg(Arg::with_name("arrow").long("arrow").help(
+            "Also write the games and the results of each opening to games.arrow and \
+             openings.arrow as Arrow IPC (Feather) files",
+        ))
+        .arg(
+            Arg::with_name("fit-tolerance")
+                .long("fit-tolerance")
+                .value_name("TOLERANCE")
+                .help(
+                    "Stop the Dirichlet fit when no alpha changes by more than TOLERANCE times \
+                     its value in an iteration [default: 1e-10]",
+                ),
+        )
+        .arg(
+            Arg::with_name("fit-max-iterations")
+                .long("fit-max-iterations")
+                .value_name("ITERATIONS")
+                .help("Give up on the Dirichlet fit after ITERATIONS iterations")
+                .default_value("100000"),
+        )
+        .arg(
+            Arg::with_name("fit-initial-alpha")
+                .long("fit-initial-alpha")
+                .value_name("ALPHA")
+                .help(
+                    "Start the Dirichlet fit from ALPHA for every result, from \
+                     WHITE,DRAW,BLACK or from the method of moments estimate with 'moments'",
+                )
+                .default_value("10"),
+        )
+        .arg(
+            Arg::with_name("fit-method")
+                .long("fit-method")
+                .help(
+                    "How the Dirichlet distribution is fitted: Minka's fixed-point iteration \
+                     or Newton's method, which is faster when alpha is large",
+                )
+                .possible_values(&["fixed-point", "newton"])
+                .default_value("fixed-point"),
+        )
+        .arg(
+            Arg::with_name("bootstrap")
+                .long("bootstrap")
+                .value_name("REPLICATES")
+                .help(
+                    "Resample the openings REPLICATES times and refit to find confidence \
+                     intervals for the fit and other statistics, written to bootstrap.csv",
+                ),
+        )
+        .arg(
+            Arg::with_name("bootstrap-seed")
+                .long("bootstrap-seed")
+                .value_name("SEED")
+                .help(
+                    "The seed of the random number generator of the bootstrap and the \
+                     goodness of fit test",
+                )
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("bootstrap-games")
+                .long("bootstrap-games")
+                .help("Also resample the games of each opening in the bootstrap"),
+        )
+        .arg(Arg::with_name("goodness-of-fit").long("goodness-of-fit").help(
+            "Test how well the fit describes the openings and write the observed and expected \
+             numbers of openings with each result to goodness_of_fit.csv",
+        ))
+        .arg(
+            Arg::with_name("goodness-of-fit-replicates")
+                .long("goodness-of-fit-replicates")
+                .value_name("REPLICATES")
+                .help(
+                    "The number of books simulated from the fit for the parametric bootstrap \
+                     p-value of the goodness of fit test, 0 for none",
+                )
+                .default_value("1000"),
+        )
         .get_matches();
 
-    let pgn_file = matches.value_of("INPUT").expect("Required by clap");
+    let inputs: Vec<&str> = matches
+        .values_of("INPUT")
+        .expect("Required by clap")
+        .collect();
     let output_dir = matches.value_of("OUTPUT").expect("Required by clap");
 
-    run(pgn_file, output_dir).unwrap_or_else(|err| {
-        eprintln!("Something when wrong! Error: {}", err);
-        exit(1);
+    let plies = value_t!(matches, "plies", usize).unwrap_or_else(|err| err.exit());
+    let key = match matches.value_of("opening-key").expect("Has a default") {
+        "moves" => OpeningKey::Moves(plies),
+        "position" => OpeningKey::Position(plies),
+        _ => OpeningKey::Fen,
+    };
+    let unfinished = match matches.value_of("unfinished").expect("Has a default") {
+        "skip" => UnfinishedPolicy::Skip,
+        "draw" => UnfinishedPolicy::Draw,
+        _ => UnfinishedPolicy::Error,
+    };
+    let filter = parse_filter(&matches).unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        exit(err.exit_code());
     });
-}
-
-#[derive(Eq, Hash, PartialEq)]
-pub struct OpeningResult {
-    white_win_count: u32,
-    draw_count: u32,
-    black_win_count: u32,
-}
-
-impl OpeningResult {
-    fn total_games(&self) -> u32 {
-        self.white_win_count + self.draw_count + self.black_win_count
-    }
-    fn white_win_proportion(&self) -> f64 {
-        f64::from(self.white_win_count) / f64::from(self.total_games())
-    }
-    fn draw_proportion(&self) -> f64 {
-        f64::from(self.draw_count) / f64::from(self.total_games())
+    let mut fit = FitOptions::default();
+    if matches.is_present("fit-tolerance") {
+        fit.tolerance = value_t!(matches, "fit-tolerance", f64).unwrap_or_else(|err| err.exit());
     }
-    fn black_win_proportion(&self) -> f64 {
-        f64::from(self.black_win_count) / f64::from(self.total_games())
-    }
-}
-
-fn run(input: &str, output: &str) -> Result<()> {
-    let mut file = File::open(input)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-
-    let games = chess_pgn_parser::read_games(&contents).map_err(|_| "A parse error occurred")?;
-
-    let output_path = Path::new(output);
-    create_dir(output_path)?;
-
-    let total_games = games.len() as u32;
-    println!("Total games: {}", total_games);
-
-    let mut opening_stats = HashMap::new();
-    for game in games {
-        let fen_tags: Vec<&String> = game.tags
-            .iter()
-            .filter(|&&(ref name, _)| name == "FEN")
-            .map(|&(_, ref value)| value)
-            .collect();
-
-        let len = fen_tags.len();
-        let fen = match len {
-            0 => return Err(From::from("FEN tag not found")),
-            1 => fen_tags[0].clone(),
-            _ => return Err(From::from("Too many FEN tags found")),
-        };
-
-        let entry = opening_stats.entry(fen).or_insert(OpeningResult {
-            white_win_count: 0,
-            draw_count: 0,
-            black_win_count: 0,
-        });
-
-        match game.termination {
-            GameTermination::WhiteWins => {
-                entry.white_win_count += 1;
-            }
-            GameTermination::DrawnGame => {
-                entry.draw_count += 1;
-            }
-            GameTermination::BlackWins => {
-                entry.black_win_count += 1;
-            }
-            _ => {
-                return Err(From::from("Bad game termination found"));
-            }
-        }
-    }
-
-    println!("Total openings: {}", opening_stats.len());
-    print_opening_stats(
-        File::create(output_path.join("opening_stats.csv"))?,
-        &opening_stats,
-    )?;
-
-    let mut wdb_counts = HashMap::new();
-    for result in opening_stats.values() {
-        let entry = wdb_counts.entry(result).or_insert(0);
-        *entry += 1;
-    }
-
-    print_wdb_counts(
-        File::create(output_path.join("wdb_counts.csv"))?,
-        &wdb_counts,
-    )?;
-    print_scatter_plot_svg(
-        File::create(output_path.join("scatter_plot.svg"))?,
-        &wdb_counts,
-    )?;
-
-    let samples: Vec<[u32; 3]> = opening_stats
-        .values()
-        .map(|results| {
-            [
-                results.white_win_count,
-                results.draw_count,
-                results.black_win_count,
-            ]
+    fit.max_iterations =
+        value_t!(matches, "fit-max-iterations", u32).unwrap_or_else(|err| err.exit());
+    fit.initial_alpha = match matches.value_of("fit-initial-alpha").expect("Has a default") {
+        "moments" => InitialAlpha::Moments,
+        value => InitialAlpha::Value(parse_alpha(value).unwrap_or_else(|err| {
+            eprintln!("Error: {}", err);
+            exit(err.exit_code());
+        })),
+    };
+    fit.method = match matches.value_of("fit-method").expect("Has a default") {
+        "newton" => FitMethod::Newton,
+        _ => FitMethod::FixedPoint,
+    };
+    let bootstrap = if matches.is_present("bootstrap") {
+        Some(BootstrapOptions {
+            replicates: value_t!(matches, "bootstrap", u32).unwrap_or_else(|err| err.exit()),
+            seed: value_t!(matches, "bootstrap-seed", u64).unwrap_or_else(|err| err.exit()),
+            resample_games: matches.is_present("bootstrap-games"),
+            ..BootstrapOptions::default()
         })
-        .collect();
-
-    let alpha = fit_polya(&samples);
-    println!(
-        "Fitted Dirichlet Alpha: ({:.3}, {:.3}, {:.3})",
-        alpha[0], alpha[1], alpha[2]
-    );
-
-    print_dirichlet_plot_svg(
-        File::create(output_path.join("dirichlet_contour_plot.svg"))?,
-        &alpha,
-    )?;
+    } else {
+        None
+    };
+    let goodness_of_fit = if matches.is_present("goodness-of-fit") {
+        Some(GoodnessOfFitOptions {
+            replicates: value_t!(matches, "goodness-of-fit-replicates", u32)
+                .unwrap_or_else(|err| err.exit()),
+            seed: value_t!(matches, "bootstrap-seed", u64).unwrap_or_else(|err| err.exit()),
+        })
+    } else {
+        None
+    };
+    let options = Options {
+        filter,
+        aggregation: AggregationOptions {
+            key,
+            normalize_fen: !matches.is_present("raw-fen"),
+            unfinished,
+            perspective: match matches.value_of("player") {
+                Some(player) => Perspective::Player(player.to_string()),
+                None => Perspective::Colour,
+            },
+        },
+        lenient: matches.is_present("lenient"),
+        matchups: match matches.value_of("matchups") {
+            Some("ordered") => Some(MatchupGrouping::Ordered),
+            Some(_) => Some(MatchupGrouping::Unordered),
+            None => None,
+        },
+        pairs: matches.is_present("pairs"),
+        openings_json: matches.is_present("openings-json"),
+        stats_format: match matches.value_of("stats-format").expect("Has a default") {
+            "detailed" => StatsFormat::Detailed,
+            _ => StatsFormat::Proportions,
+        },
+        sort_by: match matches.value_of("sort-by").expect("Has a default") {
+            "total" => SortBy::Total,
+            "draw-rate" => SortBy::DrawRate,
+            "score" => SortBy::Score,
+            "imbalance" => SortBy::Imbalance,
+            "count" => SortBy::Count,
+            _ => SortBy::Key,
+        },
+        database: matches.value_of("sqlite").map(PathBuf::from),
+        arrow: matches.is_present("arrow"),
+        fit,
+        bootstrap,
+        goodness_of_fit,
+    };
 
-    Ok(())
+    cetoba::run(&inputs, output_dir, &options).unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        exit(err.exit_code());
+    });
 }
 
-fn print_opening_stats<T: Write>(
-    mut file: T,
-    opening_stats: &HashMap<String, OpeningResult>,
-) -> Result<()> {
-    writeln!(&mut file, "FEN,total,white_win,draw,black_win")?;
-    for (fen, result) in opening_stats {
-        writeln!(
-            &mut file,
-            "{},{},{},{},{}",
-            fen.split(' ')
-                .next()
-                .expect("split always has one at least one value"),
-            result.total_games(),
-            result.white_win_proportion(),
-            result.draw_proportion(),
-            result.black_win_proportion(),
-        )?;
-    }
-    Ok(())
+fn parse_filter(matches: &ArgMatches) -> cetoba::Result<GameFilter> {
+    let parse_tag_filters = |name| -> cetoba::Result<Vec<TagFilter>> {
+        matches
+            .values_of(name)
+            .map_or(Vec::new(), |values| values.collect())
+            .into_iter()
+            .map(TagFilter::parse)
+            .collect()
+    };
+
+    Ok(GameFilter {
+        include: parse_tag_filters("include")?,
+        exclude: parse_tag_filters("exclude")?,
+        date_from: match matches.value_of("date-from") {
+            Some(date) => Some(parse_date(date)?),
+            None => None,
+        },
+        date_to: match matches.value_of("date-to") {
+            Some(date) => Some(parse_date(date)?),
+            None => None,
+        },
+    })
 }
 
-fn print_wdb_counts<T: Write>(
-    mut file: T,
-    wdb_counts: &HashMap<&OpeningResult, u32>,
-) -> Result<()> {
-    writeln!(&mut file, "WDB,Count")?;
-    for (result, count) in wdb_counts {
-        writeln!(
-            &mut file,
-            "{}-{}-{},{}",
-            result.white_win_proportion(),
-            result.draw_proportion(),
-            result.black_win_proportion(),
-            count
-        )?;
+// Parses one alpha for every result or one for each of white wins, draws and black wins
+fn parse_alpha(value: &str) -> cetoba::Result<[f64; 3]> {
+    let invalid = || format!("Invalid initial alpha '{}'", value);
+    let values = value
+        .split(',')
+        .map(|value| match value.trim().parse::<f64>() {
+            Ok(alpha) if alpha > 0. && alpha.is_finite() => Ok(alpha),
+            _ => Err(invalid()),
+        })
+        .collect::<Result<Vec<f64>, String>>()?;
+    match values.len() {
+        1 => Ok([values[0]; 3]),
+        3 => Ok([values[0], values[1], values[2]]),
+        _ => Err(From::from(invalid())),
     }
-    Ok(())
 }
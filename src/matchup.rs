@@ -0,0 +1,97 @@
+use std::collections::HashSet;
+
+use chess_pgn_parser::Game;
+
+use stats::PolyaFit;
+use OpeningResult;
+
+/// How games are grouped by the players in them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MatchupGrouping {
+    /// Games between the same White and Black players are grouped together so games where
+    /// the players swap colours are in different groups.
+    Ordered,
+    /// Games between the same two players are grouped together whichever colour they played.
+    Unordered,
+}
+
+impl MatchupGrouping {
+    /// The name of the matchup of the game e.g. `stockfish vs leela`.
+    ///
+    /// Unknown players are named `?`. The players of unordered matchups are sorted by name.
+    pub fn matchup(&self, game: &Game) -> String {
+        let white = player(game, "White");
+        let black = player(game, "Black");
+        if *self == MatchupGrouping::Unordered && black < white {
+            format!("{} vs {}", black, white)
+        } else {
+            format!("{} vs {}", white, black)
+        }
+    }
+}
+
+/// The name of the directory the results of a matchup are written to.
+///
+/// Characters other than ASCII letters, digits, `-`, `_` and `.` are replaced by `_`. Different
+/// matchups can give the same name this way, or names that differ only in case, so a name
+/// already in `used` gets a numeric suffix e.g. `M_ller_vs_X_2`. The name is added to `used`.
+pub fn directory_name(matchup: &str, used: &mut HashSet<String>) -> String {
+    let base: String = matchup
+        .chars()
+        .map(|char| {
+            if char.is_ascii_alphanumeric() || char == '-' || char == '_' || char == '.' {
+                char
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let mut name = base.clone();
+    let mut suffix = 1;
+    // Case-insensitive file systems would still put names differing in case together
+    while !used.insert(name.to_ascii_lowercase()) {
+        suffix += 1;
+        name = format!("{}_{}", base, suffix);
+    }
+    name
+}
+
+/// The name of the player in the `White` or `Black` tag of `game`, or `?` if it's unknown.
+pub fn player<'a>(game: &'a Game, tag: &str) -> &'a str {
+    game.tags
+        .iter()
+        .find(|&&(ref name, _)| name == tag)
+        .map(|&(_, ref value)| value.trim())
+        .filter(|value| !value.is_empty())
+        .unwrap_or("?")
+}
+
+/// The combined results of the games of a matchup.
+#[derive(Clone, Debug)]
+pub struct MatchupSummary {
+    pub matchup: String,
+    /// The directory the results of the matchup were written to, relative to the output.
+    pub directory: String,
+    pub opening_count: usize,
+    pub result: OpeningResult,
+    /// The Dirichlet fit of the openings of the matchup, which may not have converged.
+    pub fit: PolyaFit,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directory_names_are_unique() {
+        let mut used = HashSet::new();
+        let names: Vec<String> = ["M\u{fc}ller vs X", "M_ller vs X", "m_ller vs x", "a vs b"]
+            .iter()
+            .map(|matchup| directory_name(matchup, &mut used))
+            .collect();
+        assert_eq!(
+            names,
+            vec!["M_ller_vs_X", "M_ller_vs_X_2", "m_ller_vs_x_3", "a_vs_b"]
+        );
+    }
+}
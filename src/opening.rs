@@ -0,0 +1,537 @@
+use std::collections::{HashMap, HashSet};
+
+use chess_pgn_parser::{Game, GameTermination};
+
+use board::{canonical_fen, format_san, Position};
+use {Error, ErrorKind, Result};
+
+/// How the opening of a game is identified.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum OpeningKey {
+    /// The value of the FEN tag.
+    #[default]
+    Fen,
+    /// The SAN of the first `n` plies, preceded by the FEN tag if the game has one.
+    /// This matches books of move sequences played from the start position.
+    Moves(usize),
+    /// The FEN of the position reached after the first `n` plies. Games with fewer plies are
+    /// skipped.
+    Position(usize),
+}
+
+impl OpeningKey {
+    /// Whether the keys are FENs.
+    pub fn is_fen(&self) -> bool {
+        match *self {
+            OpeningKey::Fen | OpeningKey::Position(_) => true,
+            OpeningKey::Moves(_) => false,
+        }
+    }
+
+    /// Whether `game` has fewer plies than are needed to reach the position that identifies
+    /// its opening. Such games are skipped.
+    pub fn is_too_short(&self, game: &Game) -> bool {
+        match *self {
+            OpeningKey::Position(plies) => game.moves.len() < plies,
+            OpeningKey::Fen | OpeningKey::Moves(_) => false,
+        }
+    }
+
+    /// The opening of `game`.
+    pub fn key(&self, game: &Game) -> Result<String> {
+        let fen_tags: Vec<&String> = game.tags
+            .iter()
+            .filter(|&&(ref name, _)| name == "FEN")
+            .map(|&(_, ref value)| value)
+            .collect();
+
+        if fen_tags.len() > 1 {
+            return Err(Error::new(ErrorKind::DuplicateFen).with_tag("FEN", fen_tags[1]));
+        }
+        let fen = fen_tags.first();
+
+        match *self {
+            OpeningKey::Fen => match fen {
+                Some(fen) => Ok((*fen).clone()),
+                None => Err(From::from(ErrorKind::MissingFen)),
+            },
+            OpeningKey::Moves(plies) => {
+                let moves: Vec<String> = game.moves
+                    .iter()
+                    .take(plies)
+                    .map(|game_move| format_san(&game_move.move_.move_))
+                    .collect();
+                match fen {
+                    Some(fen) => Ok(format!("{} {}", fen, moves.join(" "))),
+                    None => Ok(moves.join(" ")),
+                }
+            }
+            OpeningKey::Position(plies) => {
+                let mut position = match fen {
+                    Some(fen) => Position::from_fen(fen).map_err(|err| err.with_tag("FEN", fen))?,
+                    None => Position::start(),
+                };
+                for game_move in game.moves.iter().take(plies) {
+                    position.play(&game_move.move_.move_)?;
+                }
+                Ok(position.to_fen())
+            }
+        }
+    }
+}
+
+/// The aggregated results of all the games played from a single opening.
+///
+/// With `Perspective::Player` the white win and black win counts are the wins and losses of the
+/// player.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct OpeningResult {
+    pub white_win_count: u32,
+    pub draw_count: u32,
+    pub black_win_count: u32,
+}
+
+impl OpeningResult {
+    pub fn total_games(&self) -> u32 {
+        self.white_win_count + self.draw_count + self.black_win_count
+    }
+    pub fn white_win_proportion(&self) -> f64 {
+        f64::from(self.white_win_count) / f64::from(self.total_games())
+    }
+    pub fn draw_proportion(&self) -> f64 {
+        f64::from(self.draw_count) / f64::from(self.total_games())
+    }
+    pub fn black_win_proportion(&self) -> f64 {
+        f64::from(self.black_win_count) / f64::from(self.total_games())
+    }
+
+    /// The points scored by White, or the player, as a fraction of the games.
+    pub fn score(&self) -> f64 {
+        (f64::from(self.white_win_count) + f64::from(self.draw_count) / 2.)
+            / f64::from(self.total_games())
+    }
+
+    /// Returns the counts in the order expected by `stats::fit_polya` i.e.
+    /// [white_win_count, draw_count, black_win_count].
+    pub fn counts(&self) -> [u32; 3] {
+        [self.white_win_count, self.draw_count, self.black_win_count]
+    }
+}
+
+/// The options that control how games are grouped by opening.
+#[derive(Clone, Debug)]
+pub struct AggregationOptions {
+    /// How the opening of each game is identified.
+    pub key: OpeningKey,
+    /// Whether FEN keys are rewritten in canonical form before grouping.
+    /// See [`board::canonical_fen`](board/fn.canonical_fen.html).
+    pub normalize_fen: bool,
+    /// What to do with games that didn't end in a win, draw or loss.
+    pub unfinished: UnfinishedPolicy,
+    /// Whose results are counted.
+    pub perspective: Perspective,
+}
+
+impl Default for AggregationOptions {
+    fn default() -> AggregationOptions {
+        AggregationOptions {
+            key: OpeningKey::Fen,
+            normalize_fen: true,
+            unfinished: UnfinishedPolicy::Error,
+            perspective: Perspective::Colour,
+        }
+    }
+}
+
+impl AggregationOptions {
+    /// The key of the opening of `game`, in canonical form if FEN keys are normalised.
+    pub fn opening(&self, game: &Game) -> Result<String> {
+        self.normalized_key(&self.key.key(game)?)
+    }
+
+    /// How `game` ended, with unfinished games handled by the unfinished game policy.
+    ///
+    /// Returns `None` for an unfinished game that is skipped and fails for one when the policy
+    /// is `UnfinishedPolicy::Error`.
+    pub fn termination(&self, game: &Game) -> Result<Option<GameTermination>> {
+        match game.termination {
+            GameTermination::Unknown => match self.unfinished {
+                UnfinishedPolicy::Error => {
+                    let result = game.tags
+                        .iter()
+                        .find(|&&(ref name, _)| name == "Result")
+                        .map_or("", |&(_, ref value)| value);
+                    Err(Error::new(ErrorKind::BadTermination).with_tag("Result", result))
+                }
+                UnfinishedPolicy::Skip => Ok(None),
+                UnfinishedPolicy::Draw => Ok(Some(GameTermination::DrawnGame)),
+            },
+            termination => Ok(Some(termination)),
+        }
+    }
+
+    fn normalized_key(&self, raw_key: &str) -> Result<String> {
+        if self.normalize_fen && self.key.is_fen() {
+            canonical_fen(raw_key)
+        } else {
+            Ok(raw_key.to_string())
+        }
+    }
+}
+
+/// What to do with a game whose result is unknown e.g. because it was interrupted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UnfinishedPolicy {
+    /// Leave the game out of the results and count it against its opening.
+    Skip,
+    /// Fail the analysis.
+    Error,
+    /// Count the game as a draw.
+    Draw,
+}
+
+/// Whose results are counted.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum Perspective {
+    /// White wins, draws and Black wins.
+    #[default]
+    Colour,
+    /// The wins, draws and losses of the named player, whichever colour they played. Games the
+    /// player didn't play, or played against themselves, are left out.
+    Player(String),
+}
+
+impl Perspective {
+    /// The names of the results in the order of `OpeningResult::counts` for labelling plots.
+    pub fn labels(&self) -> [&'static str; 3] {
+        match *self {
+            Perspective::Colour => ["White Win", "Draw", "Black Win"],
+            Perspective::Player(_) => ["Win", "Draw", "Loss"],
+        }
+    }
+
+    /// The names of the results in the order of `OpeningResult::counts` for CSV headers.
+    pub fn columns(&self) -> [&'static str; 3] {
+        match *self {
+            Perspective::Colour => ["white_win", "draw", "black_win"],
+            Perspective::Player(_) => ["win", "draw", "loss"],
+        }
+    }
+}
+
+/// Groups games by opening and counts the results of each group.
+///
+/// Games are added one at a time so they can be read from a stream.
+#[derive(Debug, Default)]
+pub struct OpeningAggregator {
+    options: AggregationOptions,
+    opening_stats: HashMap<String, OpeningResult>,
+    raw_keys: HashSet<String>,
+    skipped_games: HashMap<String, u32>,
+    // The results of the player with White when counting the results of a player
+    white_stats: HashMap<String, OpeningResult>,
+    other_games: u32,
+    short_games: u32,
+    total_games: u32,
+}
+
+impl OpeningAggregator {
+    /// Creates an aggregator with the default options.
+    pub fn new() -> OpeningAggregator {
+        OpeningAggregator::default()
+    }
+
+    pub fn with_options(options: AggregationOptions) -> OpeningAggregator {
+        OpeningAggregator {
+            options,
+            ..OpeningAggregator::default()
+        }
+    }
+
+    pub fn options(&self) -> &AggregationOptions {
+        &self.options
+    }
+
+    /// Counts the result of `game` against its opening.
+    ///
+    /// Fails if the opening can't be identified e.g. a game doesn't have exactly one FEN tag
+    /// when keying by FEN, or if the game didn't end in a win, draw or loss and the unfinished
+    /// game policy is `UnfinishedPolicy::Error`.
+    ///
+    /// Returns the key of the opening the game was counted against, or `None` if the game was
+    /// skipped.
+    pub fn add_game(&mut self, game: &Game) -> Result<Option<String>> {
+        // Short games aren't replayed so they can't fail
+        if self.options.key.is_too_short(game) {
+            self.short_games += 1;
+            return Ok(None);
+        }
+        let raw_key = self.options.key.key(game)?;
+        let key = self.options.normalized_key(&raw_key)?;
+
+        let termination = match self.options.termination(game)? {
+            Some(termination) => termination,
+            None => {
+                *self.skipped_games.entry(key).or_insert(0) += 1;
+                return Ok(None);
+            }
+        };
+
+        let termination = match self.options.perspective {
+            Perspective::Colour => termination,
+            Perspective::Player(ref player) => {
+                let is_white = tag_value(game, "White") == Some(player.as_str());
+                let is_black = tag_value(game, "Black") == Some(player.as_str());
+                if is_white == is_black {
+                    self.other_games += 1;
+                    return Ok(None);
+                }
+
+                let termination = if is_white {
+                    termination
+                } else {
+                    match termination {
+                        GameTermination::WhiteWins => GameTermination::BlackWins,
+                        GameTermination::BlackWins => GameTermination::WhiteWins,
+                        termination => termination,
+                    }
+                };
+                if is_white {
+                    add_result(self.white_stats.entry(key.clone()).or_default(), termination);
+                }
+                termination
+            }
+        };
+
+        add_result(self.opening_stats.entry(key.clone()).or_default(), termination);
+
+        if self.options.normalize_fen && self.options.key.is_fen() {
+            self.raw_keys.insert(raw_key);
+        }
+        self.total_games += 1;
+        Ok(Some(key))
+    }
+
+    /// The number of games counted against an opening. Skipped games aren't included.
+    pub fn total_games(&self) -> u32 {
+        self.total_games
+    }
+
+    /// The number of games left out because they weren't played by the player whose results
+    /// are counted.
+    pub fn other_games(&self) -> u32 {
+        self.other_games
+    }
+
+    /// The number of games skipped because they're too short to reach the position that
+    /// identifies their opening.
+    pub fn short_games(&self) -> u32 {
+        self.short_games
+    }
+
+    /// The number of unfinished games skipped for each opening.
+    pub fn skipped_games(&self) -> &HashMap<String, u32> {
+        &self.skipped_games
+    }
+
+    pub fn total_skipped_games(&self) -> u32 {
+        self.skipped_games.values().sum()
+    }
+
+    /// The number of distinct FEN keys that were merged into another key by normalisation.
+    pub fn merged_key_count(&self) -> usize {
+        if self.raw_keys.is_empty() {
+            0
+        } else {
+            self.raw_keys.len() - self.opening_stats.len()
+        }
+    }
+
+    pub fn opening_stats(&self) -> &HashMap<String, OpeningResult> {
+        &self.opening_stats
+    }
+
+    /// The results of the player in the games they played with White, when counting the
+    /// results of a player.
+    pub fn white_stats(&self) -> &HashMap<String, OpeningResult> {
+        &self.white_stats
+    }
+
+    pub fn into_opening_stats(self) -> HashMap<String, OpeningResult> {
+        self.opening_stats
+    }
+}
+
+fn add_result(result: &mut OpeningResult, termination: GameTermination) {
+    match termination {
+        GameTermination::WhiteWins => {
+            result.white_win_count += 1;
+        }
+        GameTermination::DrawnGame => {
+            result.draw_count += 1;
+        }
+        GameTermination::BlackWins => {
+            result.black_win_count += 1;
+        }
+        GameTermination::Unknown => unreachable!("Handled by the unfinished game policy"),
+    }
+}
+
+fn tag_value<'a>(game: &'a Game, tag: &str) -> Option<&'a str> {
+    game.tags
+        .iter()
+        .find(|&&(ref name, _)| name == tag)
+        .map(|&(_, ref value)| value.trim())
+}
+
+/// Groups the games by opening with the default
+/// [`AggregationOptions`](struct.AggregationOptions.html) and counts the results of each group.
+///
+/// The opening is the value of the FEN tag (`OpeningKey::Fen`) in canonical form, so FENs that
+/// differ only in their move counters or an en passant square without a legal capture are
+/// grouped together. Use an [`OpeningAggregator`](struct.OpeningAggregator.html) with other
+/// options to key openings by their first moves or the position after them, or to compare FENs
+/// as they are.
+///
+/// See [`OpeningAggregator::add_game`](struct.OpeningAggregator.html#method.add_game) for the
+/// reasons this can fail.
+pub fn aggregate_openings<I>(games: I) -> Result<HashMap<String, OpeningResult>>
+where
+    I: IntoIterator<Item = Game>,
+{
+    let mut aggregator = OpeningAggregator::new();
+    for game in games {
+        aggregator.add_game(&game)?;
+    }
+    Ok(aggregator.into_opening_stats())
+}
+
+/// Adds up the results of all the openings.
+pub fn total_result(opening_stats: &HashMap<String, OpeningResult>) -> OpeningResult {
+    opening_stats
+        .values()
+        .fold(OpeningResult::default(), |total, result| OpeningResult {
+            white_win_count: total.white_win_count + result.white_win_count,
+            draw_count: total.draw_count + result.draw_count,
+            black_win_count: total.black_win_count + result.black_win_count,
+        })
+}
+
+/// Counts how many openings share each distinct result.
+pub fn count_wdb(opening_stats: &HashMap<String, OpeningResult>) -> HashMap<&OpeningResult, u32> {
+    let mut wdb_counts = HashMap::new();
+    for result in opening_stats.values() {
+        let entry = wdb_counts.entry(result).or_insert(0);
+        *entry += 1;
+    }
+    wdb_counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chess_pgn_parser::read_games;
+
+    #[test]
+    fn normalisation_merges_fens_that_differ_by_counters_or_unusable_en_passant() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 3 7",
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2",
+            "4k3/8/8/3pP3/8/8/8/4K3 w - - 0 2",
+            "4r1k1/8/8/3pP3/8/8/8/4K3 w - d6 0 2",
+        ];
+        let text: String = fens
+            .iter()
+            .map(|fen| format!("[FEN \"{}\"]\n[Result \"1-0\"]\n\n1-0\n\n", fen))
+            .collect();
+        let games = read_games(&text).expect("The games are valid PGN");
+
+        let mut aggregator = OpeningAggregator::new();
+        for game in &games {
+            aggregator.add_game(game).expect("The game is counted");
+        }
+        assert_eq!(aggregator.total_games(), 6);
+        assert_eq!(aggregator.opening_stats().len(), 4);
+        assert_eq!(aggregator.merged_key_count(), 2);
+        assert_eq!(
+            aggregator
+                .opening_stats()
+                .get("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq -")
+                .map(OpeningResult::total_games),
+            Some(3)
+        );
+        assert!(
+            aggregator
+                .opening_stats()
+                .contains_key("4r1k1/8/8/3pP3/8/8/8/4K3 w - -")
+        );
+
+        let mut raw_aggregator = OpeningAggregator::with_options(AggregationOptions {
+            normalize_fen: false,
+            ..AggregationOptions::default()
+        });
+        for game in &games {
+            raw_aggregator.add_game(game).expect("The game is counted");
+        }
+        assert_eq!(raw_aggregator.opening_stats().len(), 6);
+        assert_eq!(raw_aggregator.merged_key_count(), 0);
+    }
+
+    #[test]
+    fn games_too_short_for_the_position_are_skipped() {
+        let games = read_games(
+            "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 Nc6 1-0\n\n\
+             [Result \"0-1\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bb5 0-1\n\n\
+             [Result \"1/2-1/2\"]\n\n1. e4 e5 2. Nf3 1/2-1/2\n\n\
+             [Result \"1-0\"]\n\n1. e5 1-0\n",
+        ).expect("The games are valid PGN");
+        let mut aggregator = OpeningAggregator::with_options(AggregationOptions {
+            key: OpeningKey::Position(4),
+            ..AggregationOptions::default()
+        });
+        let counted: Vec<bool> = games
+            .iter()
+            .map(|game| {
+                aggregator
+                    .add_game(game)
+                    .expect("The game is added")
+                    .is_some()
+            })
+            .collect();
+        assert_eq!(counted, vec![true, true, false, false]);
+        assert_eq!(aggregator.total_games(), 2);
+        assert_eq!(aggregator.short_games(), 2);
+        assert_eq!(
+            aggregator.opening_stats().get(
+                "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq -"
+            ),
+            Some(&OpeningResult {
+                white_win_count: 1,
+                draw_count: 0,
+                black_win_count: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn skipped_unfinished_games_have_no_opening() {
+        let games = read_games(
+            "[Result \"1-0\"]\n\n1. e4 e5 1-0\n\n\
+             [Result \"*\"]\n\n1. e4 e5 *\n",
+        ).expect("The games are valid PGN");
+        let mut aggregator = OpeningAggregator::with_options(AggregationOptions {
+            key: OpeningKey::Moves(2),
+            unfinished: UnfinishedPolicy::Skip,
+            ..AggregationOptions::default()
+        });
+        let openings: Vec<Option<String>> = games
+            .iter()
+            .map(|game| aggregator.add_game(game).expect("The game is added"))
+            .collect();
+        assert_eq!(openings, vec![Some("e4 e5".to_string()), None]);
+        assert_eq!(aggregator.total_skipped_games(), 1);
+    }
+}
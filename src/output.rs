@@ -0,0 +1,655 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::PathBuf;
+
+use serde_json::{self, Map, Value};
+
+use bootstrap::Bootstrap;
+use goodness_of_fit::GoodnessOfFit;
+use matchup::MatchupSummary;
+use pairs::{PairKey, Pentanomial};
+use stats::{posterior_imbalance, score_interval, wilson_interval, AlphaUncertainty, PolyaFit,
+            Z_95};
+use {OpeningKey, OpeningResult, Perspective};
+use Result;
+
+/// The figures of an analysis that are written to `summary.json`.
+#[derive(Clone, Debug)]
+pub struct Summary {
+    /// The number of games analysed from each input.
+    pub input_files: Vec<(PathBuf, u32)>,
+    pub total_games: u32,
+    /// The number of games left out by the game filter, if there is one.
+    pub filtered_games: Option<u32>,
+    pub skipped_games: u32,
+    /// The number of games too short to reach the position that identifies their opening.
+    pub short_games: u32,
+    /// The number of games rejected for each reason in lenient mode.
+    pub rejected_games: Option<BTreeMap<&'static str, u32>>,
+    /// The number of FEN keys merged by normalisation, if FEN keys are normalised.
+    pub merged_fen_keys: Option<usize>,
+    /// The results of all the openings.
+    pub result: OpeningResult,
+    pub opening_count: usize,
+    /// The Dirichlet fit, which may not have converged.
+    pub fit: PolyaFit,
+    /// The uncertainty of the fit, if it could be estimated.
+    pub uncertainty: Option<AlphaUncertainty>,
+    /// The bootstrap of the fit, if there was one and the fit converged.
+    pub bootstrap: Option<Bootstrap>,
+    /// The goodness of fit test, if there was one and the fit converged.
+    pub goodness_of_fit: Option<GoodnessOfFit>,
+    /// Problems with the data that may affect the results.
+    pub warnings: Vec<String>,
+}
+
+/// The columns of `opening_stats.csv`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum StatsFormat {
+    /// The results as proportions of the total, written by
+    /// [`print_opening_stats`](fn.print_opening_stats.html) or
+    /// [`print_player_opening_stats`](fn.print_player_opening_stats.html).
+    #[default]
+    Proportions,
+    /// The full key, the counts of the results, the score and the draw rate with confidence
+    /// intervals, written by
+    /// [`print_detailed_opening_stats`](fn.print_detailed_opening_stats.html).
+    Detailed,
+}
+
+/// The order of the rows of the tables of openings and distinct results.
+///
+/// Ties are broken by the key of the opening, or by the counts of the results.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SortBy {
+    /// By the key of the opening, or by the counts of the results for distinct results.
+    #[default]
+    Key,
+    /// By the number of games, most first.
+    Total,
+    /// By the proportion of draws, highest first.
+    DrawRate,
+    /// By the score of White, or the player, highest first.
+    Score,
+    /// By the size of the difference between the posterior mean proportions of white and black
+    /// wins given the fitted Dirichlet distribution, largest first.
+    /// See [`stats::posterior_imbalance`](../stats/fn.posterior_imbalance.html).
+    Imbalance,
+    /// By the number of openings with each distinct result, most first. Openings are sorted by
+    /// the number of games.
+    Count,
+}
+
+/// Sorts the openings into the order of `sort_by`, where `alpha` is the fitted Dirichlet alpha.
+pub fn sort_openings<'a>(
+    opening_stats: &'a HashMap<String, OpeningResult>,
+    sort_by: SortBy,
+    alpha: &[f64; 3],
+) -> Vec<(&'a String, &'a OpeningResult)> {
+    let mut openings: Vec<_> = opening_stats.iter().collect();
+    openings.sort_by(|left, right| {
+        compare_results(left.1, right.1, sort_by, alpha).then_with(|| left.0.cmp(right.0))
+    });
+    openings
+}
+
+/// Sorts the distinct results into the order of `sort_by`, where `alpha` is the fitted
+/// Dirichlet alpha.
+pub fn sort_wdb_counts<'a>(
+    wdb_counts: &HashMap<&'a OpeningResult, u32>,
+    sort_by: SortBy,
+    alpha: &[f64; 3],
+) -> Vec<(&'a OpeningResult, u32)> {
+    let mut results: Vec<_> = wdb_counts
+        .iter()
+        .map(|(&result, &count)| (result, count))
+        .collect();
+    results.sort_by(|left, right| {
+        let ordering = if sort_by == SortBy::Count {
+            right.1.cmp(&left.1)
+        } else {
+            compare_results(left.0, right.0, sort_by, alpha)
+        };
+        ordering.then_with(|| left.0.counts().cmp(&right.0.counts()))
+    });
+    results
+}
+
+/// Writes the results of each opening as CSV.
+///
+/// Only the piece placement field of FEN keys is written and the results are written as
+/// proportions of the total.
+pub fn print_opening_stats<T: Write>(
+    mut file: T,
+    opening_stats: &[(&String, &OpeningResult)],
+    key: OpeningKey,
+) -> Result<()> {
+    writeln!(
+        &mut file,
+        "{},total,white_win,draw,black_win",
+        if key.is_fen() { "FEN" } else { "Moves" }
+    )?;
+    for &(opening, result) in opening_stats {
+        writeln!(
+            &mut file,
+            "{},{},{},{},{}",
+            opening_name(opening, key),
+            result.total_games(),
+            result.white_win_proportion(),
+            result.draw_proportion(),
+            result.black_win_proportion(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the wins, draws and losses of a player in each opening as CSV.
+///
+/// The results are written as proportions of the total like
+/// [`print_opening_stats`](fn.print_opening_stats.html) and are followed by the number of games
+/// the player had White and their score with each colour, which is left empty when they didn't
+/// play that colour.
+pub fn print_player_opening_stats<T: Write>(
+    mut file: T,
+    opening_stats: &[(&String, &OpeningResult)],
+    white_stats: &HashMap<String, OpeningResult>,
+    key: OpeningKey,
+) -> Result<()> {
+    writeln!(
+        &mut file,
+        "{},total,win,draw,loss,white_games,white_score,black_score",
+        if key.is_fen() { "FEN" } else { "Moves" }
+    )?;
+    for &(opening, result) in opening_stats {
+        let white_result = white_stats.get(opening).cloned().unwrap_or_default();
+        let black_result = OpeningResult {
+            white_win_count: result.white_win_count - white_result.white_win_count,
+            draw_count: result.draw_count - white_result.draw_count,
+            black_win_count: result.black_win_count - white_result.black_win_count,
+        };
+        writeln!(
+            &mut file,
+            "{},{},{},{},{},{},{},{}",
+            opening_name(opening, key),
+            result.total_games(),
+            result.white_win_proportion(),
+            result.draw_proportion(),
+            result.black_win_proportion(),
+            white_result.total_games(),
+            optional_score(&white_result),
+            optional_score(&black_result),
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the results of each opening as CSV with the full key and the counts of the results.
+///
+/// The counts are followed by the score and draw rate with 95% confidence intervals, the
+/// normal approximation interval for the score and the Wilson score interval for the draw rate.
+/// The results are named by `columns` in the order of `OpeningResult::counts`.
+pub fn print_detailed_opening_stats<T: Write>(
+    mut file: T,
+    opening_stats: &[(&String, &OpeningResult)],
+    key: OpeningKey,
+    columns: &[&str; 3],
+) -> Result<()> {
+    writeln!(
+        &mut file,
+        "{},total,{},{},{},score,score_low,score_high,draw_rate,draw_rate_low,draw_rate_high",
+        if key.is_fen() { "FEN" } else { "Moves" },
+        columns[0],
+        columns[1],
+        columns[2]
+    )?;
+    for &(opening, result) in opening_stats {
+        let (score_low, score_high) = score_interval(&result.counts(), Z_95);
+        let (draw_rate_low, draw_rate_high) =
+            wilson_interval(result.draw_count, result.total_games(), Z_95);
+        writeln!(
+            &mut file,
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            quote(opening),
+            result.total_games(),
+            result.white_win_count,
+            result.draw_count,
+            result.black_win_count,
+            result.score(),
+            score_low,
+            score_high,
+            result.draw_proportion(),
+            draw_rate_low,
+            draw_rate_high,
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the number of openings with each distinct result as CSV.
+pub fn print_wdb_counts<T: Write>(
+    mut file: T,
+    wdb_counts: &[(&OpeningResult, u32)],
+) -> Result<()> {
+    writeln!(&mut file, "WDB,Count")?;
+    for &(result, count) in wdb_counts {
+        writeln!(
+            &mut file,
+            "{}-{}-{},{}",
+            result.white_win_proportion(),
+            result.draw_proportion(),
+            result.black_win_proportion(),
+            count
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the number of unfinished games skipped for each opening as CSV.
+pub fn print_skipped_games<T: Write>(
+    mut file: T,
+    skipped_games: &HashMap<String, u32>,
+    key: OpeningKey,
+) -> Result<()> {
+    writeln!(
+        &mut file,
+        "{},skipped",
+        if key.is_fen() { "FEN" } else { "Moves" }
+    )?;
+    let mut skipped_games: Vec<_> = skipped_games.iter().collect();
+    skipped_games.sort();
+    for (opening, count) in skipped_games {
+        writeln!(&mut file, "{},{}", quote(opening), count)?;
+    }
+    Ok(())
+}
+
+/// Writes the summary of an analysis as JSON.
+///
+/// The results and the fitted alpha are named by the columns of `perspective`. The alpha, its sum
+/// and mean are null when the fit didn't converge.
+pub fn print_summary_json<T: Write>(
+    mut file: T,
+    summary: &Summary,
+    perspective: &Perspective,
+) -> Result<()> {
+    let columns = perspective.columns();
+    let fit = &summary.fit;
+    let alpha = &fit.alpha;
+    let alpha_sum: f64 = alpha.iter().sum();
+
+    let input_files: Vec<Value> = summary
+        .input_files
+        .iter()
+        .map(|&(ref path, games)| {
+            json!({
+                "path": path.to_string_lossy(),
+                "games": games,
+            })
+        })
+        .collect();
+    let player = match *perspective {
+        Perspective::Colour => Value::Null,
+        Perspective::Player(ref player) => Value::from(player.as_str()),
+    };
+
+    let json = json!({
+        "input_files": input_files,
+        "total_games": summary.total_games,
+        "filtered_games": summary.filtered_games,
+        "skipped_games": summary.skipped_games,
+        "short_games": summary.short_games,
+        "rejected_games": summary.rejected_games,
+        "merged_fen_keys": summary.merged_fen_keys,
+        "player": player,
+        "openings": summary.opening_count,
+        "results": named_values(&columns, &summary.result.counts()),
+        "fit": {
+            "stop_reason": fit.stop_reason.description(),
+            "alpha": if fit.converged { Some(named_values(&columns, alpha)) } else { None },
+            "alpha_sum": if fit.converged { Some(alpha_sum) } else { None },
+            "mean": if fit.converged {
+                Some(named_values(&columns, &[
+                    alpha[0] / alpha_sum,
+                    alpha[1] / alpha_sum,
+                    alpha[2] / alpha_sum,
+                ]))
+            } else {
+                None
+            },
+            "openings": summary.opening_count,
+            "iterations": fit.iterations,
+            "log_likelihood": if fit.converged { Some(fit.log_likelihood) } else { None },
+            "uncertainty": summary.uncertainty.as_ref().map(|uncertainty| json!({
+                "confidence_level": 0.95,
+                "alpha_standard_error": named_values(&columns, &uncertainty.standard_errors),
+                "alpha_interval": named_intervals(&columns, &uncertainty.intervals),
+                "alpha_sum_standard_error": uncertainty.sum_standard_error,
+                "alpha_sum_interval": [uncertainty.sum_interval.0, uncertainty.sum_interval.1],
+                "mean_standard_error": named_values(&columns, &uncertainty.mean_standard_errors),
+                "mean_interval": named_intervals(&columns, &uncertainty.mean_intervals),
+            })),
+        },
+        "bootstrap": summary.bootstrap.as_ref().map(|bootstrap| {
+            let mut statistics = Map::new();
+            for statistic in &bootstrap.statistics {
+                // JSON has no infinity so an unbounded upper limit is written as null
+                statistics.insert(statistic.name.clone(), json!({
+                    "estimate": statistic.estimate,
+                    "standard_error": statistic.standard_error,
+                    "interval": statistic.interval.map(|interval| [interval.0, interval.1]),
+                }));
+            }
+            json!({
+                "replicates": bootstrap.replicates,
+                "unbounded_replicates": bootstrap.unbounded_replicates,
+                "failed_replicates": bootstrap.failed_replicates,
+                "confidence_level": bootstrap.confidence_level,
+                "statistics": statistics,
+            })
+        }),
+        "goodness_of_fit": summary.goodness_of_fit.as_ref().map(|goodness_of_fit| json!({
+            "chi_square": goodness_of_fit.chi_square,
+            "constraints": goodness_of_fit.constraints,
+            "degrees_of_freedom": goodness_of_fit.degrees_of_freedom,
+            "cells": goodness_of_fit.cells.len(),
+            "p_value": goodness_of_fit.p_value,
+            "bootstrap_p_value": goodness_of_fit.bootstrap_p_value,
+            "replicates": goodness_of_fit.replicates,
+            "failed_replicates": goodness_of_fit.failed_replicates,
+        })),
+        "warnings": summary.warnings,
+    });
+    serde_json::to_writer_pretty(&mut file, &json)?;
+    writeln!(&mut file)?;
+    Ok(())
+}
+
+/// Writes the statistics of a bootstrap with their standard errors and percentile intervals as
+/// CSV.
+///
+/// Standard errors and intervals that couldn't be estimated are left empty and an unbounded
+/// upper limit is written as `inf`.
+pub fn print_bootstrap<T: Write>(mut file: T, bootstrap: &Bootstrap) -> Result<()> {
+    writeln!(&mut file, "statistic,estimate,standard_error,lower,upper")?;
+    for statistic in &bootstrap.statistics {
+        let interval = match statistic.interval {
+            Some((lower, upper)) => format!("{},{}", lower, upper),
+            None => ",".to_string(),
+        };
+        writeln!(
+            &mut file,
+            "{},{},{},{}",
+            statistic.name,
+            statistic.estimate,
+            statistic
+                .standard_error
+                .map(|standard_error| standard_error.to_string())
+                .unwrap_or_default(),
+            interval
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the observed and expected number of openings in each cell of a goodness of fit test
+/// as CSV.
+///
+/// Each cell has the number of games of its openings, or their range e.g. `3-7`, and the number
+/// of counts of results pooled into it. The counts of a cell with only one are written
+/// separated by `-`, in the order of `OpeningResult::counts`.
+pub fn print_goodness_of_fit<T: Write>(
+    mut file: T,
+    goodness_of_fit: &GoodnessOfFit,
+) -> Result<()> {
+    writeln!(&mut file, "games,results,counts,observed,expected,chi_square")?;
+    for cell in &goodness_of_fit.cells {
+        let games = match cell.games {
+            (least, most) if least == most => least.to_string(),
+            (least, most) => format!("{}-{}", least, most),
+        };
+        let counts = match cell.counts {
+            Some(counts) => format!("{}-{}-{}", counts[0], counts[1], counts[2]),
+            None => String::new(),
+        };
+        writeln!(
+            &mut file,
+            "{},{},{},{},{},{}",
+            games,
+            cell.results,
+            counts,
+            cell.observed,
+            cell.expected,
+            cell.chi_square()
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the counts of the results of each opening as JSON with the full key of the opening.
+pub fn print_openings_json<T: Write>(
+    mut file: T,
+    opening_stats: &[(&String, &OpeningResult)],
+    key: OpeningKey,
+    perspective: &Perspective,
+) -> Result<()> {
+    let columns = perspective.columns();
+    let openings: Vec<Value> = opening_stats
+        .iter()
+        .map(|&(opening, result)| {
+            let mut object = Map::new();
+            object.insert(
+                if key.is_fen() { "fen" } else { "moves" }.to_string(),
+                Value::from(opening.as_str()),
+            );
+            object.insert("total".to_string(), Value::from(result.total_games()));
+            for (column, &count) in columns.iter().zip(result.counts().iter()) {
+                object.insert(column.to_string(), Value::from(count));
+            }
+            Value::Object(object)
+        })
+        .collect();
+    serde_json::to_writer_pretty(&mut file, &openings)?;
+    writeln!(&mut file)?;
+    Ok(())
+}
+
+/// Writes the name, combined results and fit of a matchup as JSON to the directory of the
+/// matchup.
+///
+/// The results are named by `columns` in the order of `OpeningResult::counts` and the alpha is
+/// null when the fit didn't converge.
+pub fn print_matchup_json<T: Write>(
+    mut file: T,
+    summary: &MatchupSummary,
+    columns: &[&str; 3],
+) -> Result<()> {
+    let fit = &summary.fit;
+    let json = json!({
+        "matchup": summary.matchup,
+        "total_games": summary.result.total_games(),
+        "openings": summary.opening_count,
+        "results": named_values(columns, &summary.result.counts()),
+        "fit": {
+            "stop_reason": fit.stop_reason.description(),
+            "alpha": if fit.converged { Some(named_values(columns, &fit.alpha)) } else { None },
+            "iterations": fit.iterations,
+            "log_likelihood": if fit.converged { Some(fit.log_likelihood) } else { None },
+        },
+    });
+    serde_json::to_writer_pretty(&mut file, &json)?;
+    writeln!(&mut file)?;
+    Ok(())
+}
+
+/// Writes the combined results and fitted Dirichlet alpha of each matchup as CSV.
+///
+/// The results are named by `columns` in the order of `OpeningResult::counts`. The `fit` column
+/// says why the fit stopped and the alpha columns are empty when it didn't converge.
+pub fn print_matchup_summary<T: Write>(
+    mut file: T,
+    summaries: &[MatchupSummary],
+    columns: &[&str; 3],
+) -> Result<()> {
+    writeln!(
+        &mut file,
+        "matchup,directory,openings,total,{0},{1},{2},fit,alpha_{0},alpha_{1},alpha_{2}",
+        columns[0],
+        columns[1],
+        columns[2]
+    )?;
+    for summary in summaries {
+        let fit = &summary.fit;
+        let alpha = if fit.converged {
+            format!("{},{},{}", fit.alpha[0], fit.alpha[1], fit.alpha[2])
+        } else {
+            ",,".to_string()
+        };
+        writeln!(
+            &mut file,
+            "{},{},{},{},{},{},{},{},{}",
+            quote(&summary.matchup),
+            summary.directory,
+            summary.opening_count,
+            summary.result.total_games(),
+            summary.result.white_win_proportion(),
+            summary.result.draw_proportion(),
+            summary.result.black_win_proportion(),
+            quote(fit.stop_reason.description()),
+            alpha,
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the points of the colour-reversed pairs of games of each opening and pair of players
+/// as CSV.
+///
+/// The points are those of the player and the counts of pairs with 0, 0.5, 1, 1.5 and 2 points
+/// are followed by the mean score and its variance as fractions of the two games.
+pub fn print_pair_stats<T: Write>(
+    mut file: T,
+    pentanomials: &BTreeMap<PairKey, Pentanomial>,
+    key: OpeningKey,
+) -> Result<()> {
+    writeln!(
+        &mut file,
+        "{},player,opponent,pairs,0,0.5,1,1.5,2,score,variance",
+        if key.is_fen() { "FEN" } else { "Moves" }
+    )?;
+    for (pair, pentanomial) in pentanomials {
+        write!(
+            &mut file,
+            "{},{},{},",
+            opening_name(&pair.opening, key),
+            quote(&pair.player),
+            quote(&pair.opponent)
+        )?;
+        print_pentanomial(&mut file, pentanomial)?;
+    }
+    Ok(())
+}
+
+/// Writes the points of the colour-reversed pairs of games of each pair of players combined
+/// across openings as CSV, in the same form as [`print_pair_stats`](fn.print_pair_stats.html).
+pub fn print_pentanomials<T: Write>(
+    mut file: T,
+    pentanomials: &BTreeMap<(&str, &str), Pentanomial>,
+) -> Result<()> {
+    writeln!(
+        &mut file,
+        "player,opponent,pairs,0,0.5,1,1.5,2,score,variance"
+    )?;
+    for (&(player, opponent), pentanomial) in pentanomials {
+        write!(&mut file, "{},{},", quote(player), quote(opponent))?;
+        print_pentanomial(&mut file, pentanomial)?;
+    }
+    Ok(())
+}
+
+fn print_pentanomial<T: Write>(file: &mut T, pentanomial: &Pentanomial) -> Result<()> {
+    let counts = &pentanomial.counts;
+    writeln!(
+        file,
+        "{},{},{},{},{},{},{},{}",
+        pentanomial.total_pairs(),
+        counts[0],
+        counts[1],
+        counts[2],
+        counts[3],
+        counts[4],
+        pentanomial.score(),
+        pentanomial.variance()
+    )?;
+    Ok(())
+}
+
+// Compares the results in the order of `sort_by`, with the largest values first
+fn compare_results(
+    left: &OpeningResult,
+    right: &OpeningResult,
+    sort_by: SortBy,
+    alpha: &[f64; 3],
+) -> Ordering {
+    let (left_value, right_value) = match sort_by {
+        SortBy::Key => return Ordering::Equal,
+        SortBy::Total | SortBy::Count => {
+            return right.total_games().cmp(&left.total_games());
+        }
+        SortBy::DrawRate => (left.draw_proportion(), right.draw_proportion()),
+        SortBy::Score => (left.score(), right.score()),
+        SortBy::Imbalance => (
+            posterior_imbalance(&left.counts(), alpha).abs(),
+            posterior_imbalance(&right.counts(), alpha).abs(),
+        ),
+    };
+    right_value
+        .partial_cmp(&left_value)
+        .unwrap_or(Ordering::Equal)
+}
+
+fn named_values<V: Clone + Into<Value>>(names: &[&str; 3], values: &[V; 3]) -> Value {
+    let mut object = Map::new();
+    for (name, value) in names.iter().zip(values.iter()) {
+        object.insert(name.to_string(), value.clone().into());
+    }
+    Value::Object(object)
+}
+
+fn named_intervals(names: &[&str; 3], intervals: &[(f64, f64); 3]) -> Value {
+    let intervals = [
+        json!([intervals[0].0, intervals[0].1]),
+        json!([intervals[1].0, intervals[1].1]),
+        json!([intervals[2].0, intervals[2].1]),
+    ];
+    named_values(names, &intervals)
+}
+
+fn optional_score(result: &OpeningResult) -> String {
+    if result.total_games() == 0 {
+        String::new()
+    } else {
+        result.score().to_string()
+    }
+}
+
+// Only the piece placement field of FEN keys is written
+fn opening_name(opening: &str, key: OpeningKey) -> String {
+    if key.is_fen() {
+        quote(opening
+            .split(' ')
+            .next()
+            .expect("split always has one at least one value"))
+    } else {
+        quote(opening)
+    }
+}
+
+// Quotes a CSV field if it contains a separator, quote or line break
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
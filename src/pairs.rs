@@ -0,0 +1,174 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use chess_pgn_parser::{Game, GameTermination};
+
+use matchup::player;
+use {AggregationOptions, Result};
+
+/// The points a pair of games can score: 0, 0.5, 1, 1.5 and 2.
+pub const PAIR_POINTS: [f64; 5] = [0.0, 0.5, 1.0, 1.5, 2.0];
+
+/// The number of pairs of games that scored each of `PAIR_POINTS`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Pentanomial {
+    pub counts: [u32; 5],
+}
+
+impl Pentanomial {
+    pub fn total_pairs(&self) -> u32 {
+        self.counts.iter().sum()
+    }
+
+    /// The mean score of a pair as a fraction of its two games.
+    pub fn score(&self) -> f64 {
+        self.moment(1)
+    }
+
+    /// The variance of the score of a pair as a fraction of its two games.
+    pub fn variance(&self) -> f64 {
+        let score = self.score();
+        self.moment(2) - score * score
+    }
+
+    pub fn add(&mut self, other: &Pentanomial) {
+        for (count, other_count) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count += *other_count;
+        }
+    }
+
+    // The mean of the nth power of the score of a pair
+    fn moment(&self, n: i32) -> f64 {
+        let sum: f64 = self.counts
+            .iter()
+            .enumerate()
+            .map(|(index, &count)| f64::from(count) * (index as f64 / 4.0).powi(n))
+            .sum();
+        sum / f64::from(self.total_pairs())
+    }
+}
+
+/// An opening played by two players with colours reversed.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct PairKey {
+    pub opening: String,
+    /// The player whose points are counted, the first of the two by name.
+    pub player: String,
+    pub opponent: String,
+}
+
+/// Pairs up games of the same opening between the same players with colours reversed and
+/// counts the points of each pair.
+///
+/// Each game is paired with the earliest unpaired game of its opening in which the players had
+/// the other colours. The points are counted for the player whose name comes first so the
+/// results of a pair of players can be combined across openings.
+#[derive(Debug, Default)]
+pub struct PairAggregator {
+    options: AggregationOptions,
+    // The results of games waiting for their partner, by opening, White and Black
+    unpaired: HashMap<(String, String, String), VecDeque<GameTermination>>,
+    pentanomials: BTreeMap<PairKey, Pentanomial>,
+}
+
+impl PairAggregator {
+    pub fn with_options(options: AggregationOptions) -> PairAggregator {
+        PairAggregator {
+            options,
+            ..PairAggregator::default()
+        }
+    }
+
+    /// Pairs `game` with an earlier game or holds on to it until its partner is added.
+    ///
+    /// Fails for the same reasons as
+    /// [`OpeningAggregator::add_game`](../struct.OpeningAggregator.html#method.add_game).
+    /// Skipped unfinished games and games too short for their opening are never paired.
+    pub fn add_game(&mut self, game: &Game) -> Result<()> {
+        if self.options.key.is_too_short(game) {
+            return Ok(());
+        }
+        let opening = self.options.opening(game)?;
+        let termination = match self.options.termination(game)? {
+            Some(termination) => termination,
+            None => return Ok(()),
+        };
+        let white = player(game, "White").to_string();
+        let black = player(game, "Black").to_string();
+
+        let partner = self.unpaired
+            .get_mut(&(opening.clone(), black.clone(), white.clone()))
+            .and_then(|partners| partners.pop_front());
+        let earlier = match partner {
+            Some(earlier) => earlier,
+            None => {
+                self.unpaired
+                    .entry((opening, white, black))
+                    .or_default()
+                    .push_back(termination);
+                return Ok(());
+            }
+        };
+
+        // The player was White in the earlier game when their name comes first, which is
+        // also the case when a player plays itself
+        let (key, points) = if black <= white {
+            let points = white_points(earlier) + 2 - white_points(termination);
+            (
+                PairKey {
+                    opening,
+                    player: black,
+                    opponent: white,
+                },
+                points,
+            )
+        } else {
+            let points = 2 - white_points(earlier) + white_points(termination);
+            (
+                PairKey {
+                    opening,
+                    player: white,
+                    opponent: black,
+                },
+                points,
+            )
+        };
+        self.pentanomials
+            .entry(key)
+            .or_default()
+            .counts[points] += 1;
+        Ok(())
+    }
+
+    /// The number of games that haven't been paired.
+    pub fn unpaired_games(&self) -> usize {
+        self.unpaired.values().map(VecDeque::len).sum()
+    }
+
+    /// The points of the pairs of each opening and pair of players.
+    pub fn pentanomials(&self) -> &BTreeMap<PairKey, Pentanomial> {
+        &self.pentanomials
+    }
+
+    /// The points of the pairs of each pair of players combined across openings, by player
+    /// and opponent.
+    pub fn matchup_pentanomials(&self) -> BTreeMap<(&str, &str), Pentanomial> {
+        let mut totals = BTreeMap::new();
+        for (key, pentanomial) in &self.pentanomials {
+            totals
+                .entry((key.player.as_str(), key.opponent.as_str()))
+                .or_insert_with(Pentanomial::default)
+                .add(pentanomial);
+        }
+        totals
+    }
+}
+
+// The points of White in half points
+fn white_points(termination: GameTermination) -> usize {
+    match termination {
+        GameTermination::WhiteWins => 2,
+        GameTermination::DrawnGame => 1,
+        GameTermination::BlackWins => 0,
+        GameTermination::Unknown => unreachable!("Handled by the unfinished game policy"),
+    }
+}
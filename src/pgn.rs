@@ -0,0 +1,366 @@
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::mem;
+
+use chess_pgn_parser::{read_games, Game};
+
+use {Error, ErrorKind, Result};
+
+// The game termination markers that end the movetext of a game
+const TERMINATIONS: &[&str] = &["1-0", "0-1", "1/2-1/2", "*"];
+
+/// Where a game starts in its input.
+///
+/// For compressed input the line and byte are positions in the decompressed text.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct GamePosition {
+    /// The number of the game in its input, starting at 1.
+    pub game_index: u64,
+    /// The line of the first tag or move of the game, starting at 1.
+    pub line: u64,
+    /// The offset of the start of that line, starting at 0.
+    pub byte: u64,
+}
+
+/// Reads the games of a PGN one at a time.
+///
+/// Only the text of the game being parsed is held in memory so the memory use doesn't depend
+/// on the size of the input. The text of each game is decoded on its own so a game that isn't
+/// valid UTF-8 is an error for that game only.
+pub struct GameReader<R> {
+    reader: R,
+    line: Vec<u8>,
+    // The first line of the next game, read while looking for the end of the previous one
+    pending: Vec<u8>,
+    pending_position: GamePosition,
+    parsed: VecDeque<Game>,
+    parsed_position: GamePosition,
+    // The text the games in `parsed` were parsed from
+    text: String,
+    position: GamePosition,
+    // The position after the last line read
+    next_line: u64,
+    next_byte: u64,
+    game_count: u64,
+    finished: bool,
+}
+
+impl<R: BufRead> GameReader<R> {
+    pub fn new(reader: R) -> GameReader<R> {
+        GameReader {
+            reader,
+            line: Vec::new(),
+            pending: Vec::new(),
+            pending_position: GamePosition::default(),
+            parsed: VecDeque::new(),
+            parsed_position: GamePosition::default(),
+            text: String::new(),
+            position: GamePosition::default(),
+            next_line: 1,
+            next_byte: 0,
+            game_count: 0,
+            finished: false,
+        }
+    }
+
+    /// The position of the game last returned by `next`.
+    pub fn position(&self) -> &GamePosition {
+        &self.position
+    }
+
+    /// The PGN text of the game last returned by `next`, including when it failed to parse.
+    /// Invalid UTF-8 is replaced by the replacement character.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    // Reads the text of the next game. A game ends when a tag line is found after its movetext
+    // outside a brace comment, or at a blank line after a termination marker. A tag line after
+    // a blank line also ends an unterminated comment so a bad game can't take in the rest of
+    // the input. Returns empty text at the end of the input.
+    fn read_game_text(&mut self) -> Result<(Vec<u8>, GamePosition)> {
+        let mut text = mem::take(&mut self.pending);
+        let mut start = mem::take(&mut self.pending_position);
+        let mut in_movetext = false;
+        // Brace comments don't nest so a comment ends at the first `}`
+        let mut in_comment = false;
+        let mut terminated = false;
+        let mut after_blank = false;
+
+        loop {
+            self.line.clear();
+            let line_position = GamePosition {
+                game_index: 0,
+                line: self.next_line,
+                byte: self.next_byte,
+            };
+            let length = self.reader.read_until(b'\n', &mut self.line).map_err(|err| {
+                Error::from(err).at(&GamePosition {
+                    game_index: self.game_count + 1,
+                    ..line_position.clone()
+                })
+            })?;
+            if length == 0 {
+                self.finished = true;
+                return Ok((text, start));
+            }
+            self.next_line += 1;
+            self.next_byte += length as u64;
+
+            // Invalid UTF-8 is only found once the game has been read
+            let line = String::from_utf8_lossy(&self.line);
+            let trimmed = line.trim_start();
+            let blank = trimmed.is_empty();
+            if trimmed.starts_with('[') && (!in_comment || after_blank) {
+                if in_movetext {
+                    self.pending.extend_from_slice(&self.line);
+                    self.pending_position = line_position;
+                    return Ok((text, start));
+                }
+            } else if blank {
+                if terminated && !in_comment {
+                    return Ok((text, start));
+                }
+            } else {
+                in_movetext = true;
+                terminated |= scan_movetext(trimmed, &mut in_comment);
+            }
+            after_blank = blank;
+
+            if is_blank(&text) && !blank {
+                start = line_position;
+            }
+            text.extend_from_slice(&self.line);
+        }
+    }
+}
+
+fn is_blank(text: &[u8]) -> bool {
+    text.iter().all(|byte| byte.is_ascii_whitespace())
+}
+
+// Follows the brace comments of a line of movetext and returns whether it has a termination
+// marker outside them
+fn scan_movetext(line: &str, in_comment: &mut bool) -> bool {
+    let mut visible = String::new();
+    for char in line.chars() {
+        match char {
+            '{' if !*in_comment => {
+                *in_comment = true;
+                visible.push(' ');
+            }
+            '}' if *in_comment => *in_comment = false,
+            // The rest of the line is a comment
+            ';' if !*in_comment => break,
+            _ if !*in_comment => visible.push(char),
+            _ => {}
+        }
+    }
+    visible
+        .split_whitespace()
+        .any(|token| TERMINATIONS.contains(&token))
+}
+
+impl<R: BufRead> Iterator for GameReader<R> {
+    type Item = Result<Game>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.parsed.is_empty() {
+            if self.finished {
+                return None;
+            }
+
+            let (text, start) = match self.read_game_text() {
+                Ok(value) => value,
+                Err(err) => {
+                    self.finished = true;
+                    return Some(Err(err));
+                }
+            };
+
+            if is_blank(&text) {
+                continue;
+            }
+
+            self.parsed_position = start;
+            let parsed = match String::from_utf8(text) {
+                Ok(text) => {
+                    self.text = text;
+                    read_games(&self.text).map_err(|_| ErrorKind::Parse)
+                }
+                Err(err) => {
+                    self.text = String::from_utf8_lossy(err.as_bytes()).into_owned();
+                    Err(ErrorKind::InvalidUtf8)
+                }
+            };
+            match parsed {
+                Ok(games) => self.parsed.extend(games),
+                Err(kind) => {
+                    self.game_count += 1;
+                    self.position = GamePosition {
+                        game_index: self.game_count,
+                        ..self.parsed_position.clone()
+                    };
+                    return Some(Err(Error::new(kind).at(&self.position)));
+                }
+            }
+        }
+
+        self.game_count += 1;
+        self.position = GamePosition {
+            game_index: self.game_count,
+            ..self.parsed_position.clone()
+        };
+        self.parsed.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ErrorKind;
+
+    // The White tags of the games read from `text`
+    fn read_white_tags(text: &[u8]) -> Vec<String> {
+        GameReader::new(text)
+            .map(|game| {
+                let game = game.expect("The game is read");
+                game.tags
+                    .iter()
+                    .find(|&&(ref name, _)| name == "White")
+                    .map(|&(_, ref value)| value.clone())
+                    .expect("The game has a White tag")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_every_game() {
+        let text = b"[White \"a\"]\n[Result \"1-0\"]\n\n1. e4 e5 1-0\n\n\
+                     [White \"b\"]\n[Result \"0-1\"]\n\n1. d4 d5 0-1\n\n\
+                     [White \"c\"]\n[Result \"1/2-1/2\"]\n\n1. c4 c5 1/2-1/2\n";
+        assert_eq!(read_white_tags(text), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn reads_the_last_game_without_a_newline() {
+        let text = b"[White \"a\"]\n[Result \"1-0\"]\n\n1. e4 e5 1-0\n\n\
+                     [White \"b\"]\n[Result \"0-1\"]\n\n1. d4 d5 0-1";
+        assert_eq!(read_white_tags(text), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn tag_lines_in_comments_dont_start_a_game() {
+        let text = b"[White \"a\"]\n[Result \"1-0\"]\n\n1. e4 {a comment\n\
+                     [White \"b\"]\n} e5 1-0\n\n\
+                     [White \"c\"]\n[Result \"0-1\"]\n\n1. d4 d5 0-1\n";
+        assert_eq!(read_white_tags(text), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn brace_comments_dont_nest() {
+        let text = b"[White \"a\"]\n[Result \"1-0\"]\n\n1. e4 {a { comment} e5 1-0\n\n\
+                     [White \"b\"]\n[Result \"0-1\"]\n\n1. d4 d5 0-1\n";
+        assert_eq!(read_white_tags(text), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn braces_in_line_comments_are_ignored() {
+        let text = b"[White \"a\"]\n[Result \"1-0\"]\n\n1. e4 ; a { comment\ne5 1-0\n\n\
+                     [White \"b\"]\n[Result \"0-1\"]\n\n1. d4 d5 0-1\n";
+        assert_eq!(read_white_tags(text), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn unterminated_comments_end_at_the_next_game() {
+        let text = b"[White \"a\"]\n[Result \"1-0\"]\n\n1. e4 {a comment\ne5 1-0\n\n\
+                     [White \"b\"]\n[Result \"0-1\"]\n\n1. d4 d5 0-1\n\n\
+                     [White \"c\"]\n[Result \"1/2-1/2\"]\n\n1. c4 c5 1/2-1/2\n";
+        let mut reader = GameReader::new(&text[..]);
+        let err = reader
+            .next()
+            .expect("There is a game")
+            .expect_err("The first game isn't valid");
+        match *err.kind() {
+            ErrorKind::Parse => {}
+            ref kind => panic!("Unexpected error {:?}", kind),
+        }
+        assert_eq!(reader.text().lines().count(), 6);
+        let white_tags: Vec<String> = reader
+            .map(|game| {
+                let game = game.expect("The game is read");
+                game.tags[0].1.clone()
+            })
+            .collect();
+        assert_eq!(white_tags, vec!["b", "c"]);
+    }
+
+    #[test]
+    fn games_without_tags_end_at_their_termination() {
+        let text = b"1. e4 e5 1-0\n\n1. d4 {1-0\n\n} d5 0-1\n\n1. c4 c5 *\n";
+        let mut reader = GameReader::new(&text[..]);
+        let mut lines = Vec::new();
+        while let Some(game) = reader.next() {
+            game.expect("The game is read");
+            lines.push(reader.position().line);
+        }
+        assert_eq!(lines, vec![1, 3, 7]);
+    }
+
+    #[test]
+    fn positions_are_the_start_of_each_game() {
+        let text = b"\n[White \"a\"]\n[Result \"1-0\"]\n\n1. e4 e5 1-0\n\n\
+                     [White \"b\"]\n[Result \"0-1\"]\n\n1. d4 d5 0-1\n";
+        let mut reader = GameReader::new(&text[..]);
+        let mut positions = Vec::new();
+        while let Some(game) = reader.next() {
+            game.expect("The game is read");
+            positions.push(reader.position().clone());
+        }
+        assert_eq!(
+            positions,
+            vec![
+                GamePosition {
+                    game_index: 1,
+                    line: 2,
+                    byte: 1,
+                },
+                GamePosition {
+                    game_index: 2,
+                    line: 7,
+                    byte: 43,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_is_an_error_for_its_game() {
+        let text = b"[White \"a\"]\n[Result \"1-0\"]\n\n1. e4 e5 1-0\n\n\
+                     [White \"M\xfcller\"]\n[Result \"0-1\"]\n\n1. d4 d5 0-1\n\n\
+                     [White \"c\"]\n[Result \"1/2-1/2\"]\n\n1. c4 c5 1/2-1/2\n";
+        let mut reader = GameReader::new(&text[..]);
+        reader
+            .next()
+            .expect("There is a game")
+            .expect("The first game is read");
+        let err = reader
+            .next()
+            .expect("There is an error")
+            .expect_err("The second game isn't read");
+        match *err.kind() {
+            ErrorKind::InvalidUtf8 => {}
+            ref kind => panic!("Unexpected error {:?}", kind),
+        }
+        assert!(err.kind().is_game_error());
+        assert_eq!(err.position().map(|position| position.line), Some(6));
+        assert!(reader.text().contains("M\u{fffd}ller"));
+        let game = reader
+            .next()
+            .expect("There is a game")
+            .expect("The third game is read");
+        assert_eq!(game.tags[0].1, "c");
+        assert!(reader.next().is_none());
+    }
+}
@@ -28,19 +28,27 @@ const W_CORNER: Point = (0., SIDE);
 const D_CORNER: Point = (SIDE / 2., SIDE * (1. - ALTITUDE_RATIO));
 const L_CORNER: Point = (SIDE, SIDE);
 
+/// Writes a ternary scatter plot of the distinct results as SVG.
+/// The area of each point is proportional to the number of openings with that result.
+///
+/// The axes are labelled with `labels` in the order of `OpeningResult::counts`.
 pub fn print_scatter_plot_svg<T: Write>(
     mut file: T,
     wdl_counts: &HashMap<&OpeningResult, u32>,
+    labels: &[&str; 3],
 ) -> Result<()> {
     writeln!(&mut file, "{}", include_str!("svg_start.txt"))?;
     writeln!(&mut file, "{}", include_str!("scatter_style.txt"))?;
 
     draw_main_group_start(&mut file)?;
 
-    draw_main_triangle(&mut file)?;
+    draw_main_triangle(&mut file, labels)?;
 
     let max_count = f64::from(wdl_counts.values().cloned().max().unwrap_or(1));
 
+    let mut wdl_counts: Vec<_> = wdl_counts.iter().collect();
+    wdl_counts.sort_by_key(|&(result, _)| result.counts());
+
     for (result, count) in wdl_counts {
         writeln!(
             &mut file,
@@ -55,7 +63,14 @@ pub fn print_scatter_plot_svg<T: Write>(
     Ok(())
 }
 
-pub fn print_dirichlet_plot_svg<T: Write>(mut file: T, alpha: &[f64; 3]) -> Result<()> {
+/// Writes a ternary contour plot of the Dirichlet distribution with parameters `alpha` as SVG.
+///
+/// The axes are labelled with `labels` in the order of `OpeningResult::counts`.
+pub fn print_dirichlet_plot_svg<T: Write>(
+    mut file: T,
+    alpha: &[f64; 3],
+    labels: &[&str; 3],
+) -> Result<()> {
     writeln!(&mut file, "{}", include_str!("svg_start.txt"))?;
     writeln!(&mut file, "{}", include_str!("dirichlet_style.txt"))?;
     draw_main_group_start(&mut file)?;
@@ -100,7 +115,7 @@ pub fn print_dirichlet_plot_svg<T: Write>(mut file: T, alpha: &[f64; 3]) -> Resu
         )?;
     }
 
-    draw_main_triangle(&mut file)?;
+    draw_main_triangle(&mut file, labels)?;
 
     writeln!(&mut file, "{}", include_str!("svg_end.txt"))?;
 
@@ -126,7 +141,7 @@ fn draw_main_group_start<T: Write>(mut file: T) -> Result<()> {
     Ok(())
 }
 
-fn draw_main_triangle<T: Write>(mut file: T) -> Result<()> {
+fn draw_main_triangle<T: Write>(mut file: T, labels: &[&str; 3]) -> Result<()> {
     writeln!(
         &mut file,
         r#"    <polygon class="main" points="0,{0:.3} {0:.3},{0:.3} {1:.3},{2:.3}" />"#,
@@ -355,11 +370,12 @@ fn draw_main_triangle<T: Write>(mut file: T) -> Result<()> {
         &mut file,
         concat!(
             r##"<text text-anchor="middle" dy="{:.3}" class="axis-label">"##,
-            r##"<textPath xlink:href="#left-arrow" startOffset="{:.3}">Draw"##,
+            r##"<textPath xlink:href="#left-arrow" startOffset="{:.3}">{}"##,
             r##"</textPath></text>"##
         ),
         AXIS_LABEL_DY,
-        SIDE / 4.
+        SIDE / 4.,
+        labels[1]
     )?;
 
     writeln!(
@@ -370,11 +386,12 @@ fn draw_main_triangle<T: Write>(mut file: T) -> Result<()> {
         &mut file,
         concat!(
             r##"<text text-anchor="middle" dy="{:.3}" class="axis-label">"##,
-            r##"<textPath xlink:href="#right-arrow" startOffset="{:.3}">Black Win"##,
+            r##"<textPath xlink:href="#right-arrow" startOffset="{:.3}">{}"##,
             r##"</textPath></text>"##
         ),
         AXIS_LABEL_DY,
-        SIDE / 4.
+        SIDE / 4.,
+        labels[2]
     )?;
 
     writeln!(
@@ -385,11 +402,12 @@ fn draw_main_triangle<T: Write>(mut file: T) -> Result<()> {
         &mut file,
         concat!(
             r##"<text text-anchor="middle" dy="{:.3}" class="axis-label">"##,
-            r##"<textPath xlink:href="#bottom-arrow-reverse" startOffset="{:.3}">White Win"##,
+            r##"<textPath xlink:href="#bottom-arrow-reverse" startOffset="{:.3}">{}"##,
             r##"</textPath></text>"##
         ),
         AXIS_LABEL_FONT_SIZE - AXIS_LABEL_DY - 2.,
-        SIDE / 4.
+        SIDE / 4.,
+        labels[0]
     )?;
 
     Ok(())
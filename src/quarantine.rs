@@ -0,0 +1,45 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use {Error, Result};
+
+/// Collects the games rejected in lenient mode.
+///
+/// Each game is written as PGN preceded by an escaped line with the reason it was rejected
+/// e.g. `% Rejected: FEN tag not found in game 3 at line 25 (byte 1042) of results.pgn`.
+pub struct Quarantine<W> {
+    writer: W,
+    counts: BTreeMap<&'static str, u32>,
+}
+
+impl<W: Write> Quarantine<W> {
+    pub fn new(writer: W) -> Quarantine<W> {
+        Quarantine {
+            writer,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Writes the PGN `text` of a game rejected because of `err`.
+    pub fn reject(&mut self, err: &Error, text: &str) -> Result<()> {
+        *self.counts.entry(err.kind().summary()).or_insert(0) += 1;
+        writeln!(&mut self.writer, "% Rejected: {}", err)?;
+        writeln!(&mut self.writer, "{}", text.trim())?;
+        writeln!(&mut self.writer)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// The number of rejected games for each kind of error.
+    pub fn counts(&self) -> &BTreeMap<&'static str, u32> {
+        &self.counts
+    }
+
+    pub fn total(&self) -> u32 {
+        self.counts.values().sum()
+    }
+}
@@ -0,0 +1,572 @@
+//! Reports that combine the results of an analysis in one document.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::io::Write;
+
+use bootstrap::MAX_FAILED_SHARE;
+use output::{sort_openings, SortBy, Summary};
+use plot::{print_dirichlet_plot_svg, print_scatter_plot_svg};
+use stats::posterior_imbalance;
+use {OpeningKey, OpeningResult, Perspective, Result};
+
+// The number of openings in each of the tables of the most and least balanced openings
+const BALANCE_ROWS: usize = 5;
+
+// The widest bar of the draw rate histogram in characters
+const HISTOGRAM_WIDTH: u32 = 40;
+
+/// Writes a report of the analysis as a single HTML page.
+///
+/// The page shows the summary, the data quality warnings, the plots and a table of the openings
+/// in the order of `openings` that can be sorted by clicking the headers and filtered by key and
+/// number of games. The plots, styles and script are all included in the page so it doesn't
+/// need any other files.
+pub fn print_html_report<T: Write>(
+    mut file: T,
+    summary: &Summary,
+    openings: &[(&String, &OpeningResult)],
+    key: OpeningKey,
+    perspective: &Perspective,
+) -> Result<()> {
+    let labels = perspective.labels();
+    let alpha = &summary.fit.alpha;
+    let alpha_sum: f64 = alpha.iter().sum();
+
+    writeln!(&mut file, "<!DOCTYPE html>")?;
+    writeln!(&mut file, "<html lang=\"en\">")?;
+    writeln!(&mut file, "<head>")?;
+    writeln!(&mut file, "  <meta charset=\"utf-8\">")?;
+    writeln!(&mut file, "  <title>Opening Book Analysis</title>")?;
+    writeln!(&mut file, "  <style>")?;
+    write!(&mut file, "{}", include_str!("style.css"))?;
+    writeln!(&mut file, "  </style>")?;
+    writeln!(&mut file, "</head>")?;
+    writeln!(&mut file, "<body>")?;
+    writeln!(&mut file, "<h1>Opening Book Analysis</h1>")?;
+
+    writeln!(&mut file, "<h2>Summary</h2>")?;
+    writeln!(&mut file, "<table>")?;
+    for &(ref path, games) in &summary.input_files {
+        print_row(
+            &mut file,
+            &format!("Games in {}", escape(&path.to_string_lossy())),
+            &games.to_string(),
+        )?;
+    }
+    print_row(&mut file, "Total games", &summary.total_games.to_string())?;
+    if let Some(filtered_games) = summary.filtered_games {
+        print_row(&mut file, "Filtered out games", &filtered_games.to_string())?;
+    }
+    if let Perspective::Player(ref player) = *perspective {
+        print_row(&mut file, "Player", &escape(player))?;
+    }
+    print_row(&mut file, "Openings", &summary.opening_count.to_string())?;
+    for (index, label) in labels.iter().enumerate() {
+        print_row(
+            &mut file,
+            label,
+            &format!(
+                "{} ({:.1}%)",
+                summary.result.counts()[index],
+                100. * f64::from(summary.result.counts()[index])
+                    / f64::from(summary.result.total_games())
+            ),
+        )?;
+    }
+    if summary.fit.converged {
+        print_row(
+            &mut file,
+            "Fitted Dirichlet alpha",
+            &format!(
+                "({:.3}, {:.3}, {:.3})",
+                alpha[0], alpha[1], alpha[2]
+            ),
+        )?;
+        print_row(&mut file, "Alpha sum", &format!("{:.3}", alpha_sum))?;
+    } else {
+        print_row(
+            &mut file,
+            "Dirichlet fit",
+            &format!("failed: {}", summary.fit.stop_reason.description()),
+        )?;
+    }
+    if let Some(ref uncertainty) = summary.uncertainty {
+        for (index, label) in labels.iter().enumerate() {
+            print_row(
+                &mut file,
+                &format!("Alpha {} 95% CI", label),
+                &interval(uncertainty.intervals[index]),
+            )?;
+        }
+        print_row(
+            &mut file,
+            "Alpha sum 95% CI",
+            &interval(uncertainty.sum_interval),
+        )?;
+    }
+    writeln!(&mut file, "</table>")?;
+
+    writeln!(&mut file, "<h2>Data Quality</h2>")?;
+    if summary.warnings.is_empty() {
+        writeln!(&mut file, "<p>No problems were found.</p>")?;
+    } else {
+        writeln!(&mut file, "<ul class=\"warnings\">")?;
+        for warning in &summary.warnings {
+            writeln!(&mut file, "  <li>{}</li>", escape(warning))?;
+        }
+        writeln!(&mut file, "</ul>")?;
+    }
+
+    let mut wdb_counts = HashMap::new();
+    for &(_, result) in openings {
+        *wdb_counts.entry(result).or_insert(0) += 1;
+    }
+    let mut scatter_plot = Vec::new();
+    print_scatter_plot_svg(&mut scatter_plot, &wdb_counts, &labels)?;
+    let mut plots = vec![scatter_plot];
+    // There is no fitted distribution to plot when the fit failed
+    if summary.fit.converged {
+        let mut dirichlet_plot = Vec::new();
+        print_dirichlet_plot_svg(&mut dirichlet_plot, alpha, &labels)?;
+        plots.push(dirichlet_plot);
+    }
+
+    writeln!(&mut file, "<h2>Plots</h2>")?;
+    writeln!(&mut file, "<div class=\"plots\">")?;
+    for plot in &plots {
+        write!(&mut file, "{}", inline_svg(&String::from_utf8_lossy(plot)))?;
+    }
+    writeln!(&mut file, "</div>")?;
+
+    writeln!(&mut file, "<h2>Openings</h2>")?;
+    writeln!(
+        &mut file,
+        concat!(
+            "<p class=\"controls\">",
+            "<label>Filter <input id=\"filter\" type=\"search\"></label> ",
+            "<label>Minimum games <input id=\"minimum\" type=\"number\" min=\"0\" value=\"0\">",
+            "</label> ",
+            "Showing <span id=\"shown\">{}</span> of {} openings</p>"
+        ),
+        openings.len(),
+        openings.len()
+    )?;
+    writeln!(&mut file, "<table id=\"openings\">")?;
+    writeln!(
+        &mut file,
+        "<thead><tr><th>{}</th><th>Total</th><th>{}</th><th>{}</th><th>{}</th>\
+         <th>Score</th><th>Draw Rate</th></tr></thead>",
+        if key.is_fen() { "FEN" } else { "Moves" },
+        labels[0],
+        labels[1],
+        labels[2]
+    )?;
+    writeln!(&mut file, "<tbody>")?;
+    for &(opening, result) in openings {
+        let opening = escape(opening);
+        writeln!(
+            &mut file,
+            "<tr><td class=\"key\" data-value=\"{0}\">{0}</td>{1}{2}{3}{4}{5}{6}</tr>",
+            opening,
+            cell(&result.total_games().to_string(), f64::from(result.total_games())),
+            cell(
+                &result.white_win_count.to_string(),
+                f64::from(result.white_win_count)
+            ),
+            cell(&result.draw_count.to_string(), f64::from(result.draw_count)),
+            cell(
+                &result.black_win_count.to_string(),
+                f64::from(result.black_win_count)
+            ),
+            cell(&format!("{:.3}", result.score()), result.score()),
+            cell(
+                &format!("{:.3}", result.draw_proportion()),
+                result.draw_proportion()
+            ),
+        )?;
+    }
+    writeln!(&mut file, "</tbody>")?;
+    writeln!(&mut file, "</table>")?;
+
+    writeln!(&mut file, "<script>")?;
+    write!(&mut file, "{}", include_str!("script.js"))?;
+    writeln!(&mut file, "</script>")?;
+    writeln!(&mut file, "</body>")?;
+    writeln!(&mut file, "</html>")?;
+    Ok(())
+}
+
+/// Writes a summary of the analysis as GitHub-flavoured Markdown for pull requests and wiki
+/// pages.
+///
+/// The summary has the totals, the fitted alpha with its standard errors, the distribution of
+/// the draw rates of the openings, the most and least balanced openings and links to the plots,
+/// which are expected next to it. When the fit failed the reason is given instead of alpha and
+/// the balanced openings and contour plot, which need it, are left out.
+pub fn print_markdown_report<T: Write>(
+    mut file: T,
+    summary: &Summary,
+    opening_stats: &HashMap<String, OpeningResult>,
+    key: OpeningKey,
+    perspective: &Perspective,
+) -> Result<()> {
+    let labels = perspective.labels();
+    let alpha = &summary.fit.alpha;
+    let alpha_sum: f64 = alpha.iter().sum();
+    let openings = sort_openings(opening_stats, SortBy::Key, alpha);
+
+    writeln!(&mut file, "# Opening Book Analysis")?;
+    writeln!(&mut file)?;
+    if let Perspective::Player(ref player) = *perspective {
+        writeln!(&mut file, "Results of {}.", escape_markdown(player))?;
+        writeln!(&mut file)?;
+    }
+    writeln!(&mut file, "| | Games |")?;
+    writeln!(&mut file, "| --- | ---: |")?;
+    writeln!(&mut file, "| Analysed | {} |", summary.total_games)?;
+    if let Some(filtered_games) = summary.filtered_games {
+        writeln!(&mut file, "| Filtered out | {} |", filtered_games)?;
+    }
+    if summary.skipped_games > 0 {
+        writeln!(&mut file, "| Skipped | {} |", summary.skipped_games)?;
+    }
+    if summary.short_games > 0 {
+        writeln!(&mut file, "| Too short | {} |", summary.short_games)?;
+    }
+    if let Some(ref rejected_games) = summary.rejected_games {
+        writeln!(
+            &mut file,
+            "| Rejected | {} |",
+            rejected_games.values().sum::<u32>()
+        )?;
+    }
+    for (index, label) in labels.iter().enumerate() {
+        writeln!(
+            &mut file,
+            "| {} | {} ({:.1}%) |",
+            label,
+            summary.result.counts()[index],
+            100. * f64::from(summary.result.counts()[index])
+                / f64::from(summary.result.total_games())
+        )?;
+    }
+    writeln!(&mut file)?;
+    writeln!(&mut file, "{} openings.", summary.opening_count)?;
+    writeln!(&mut file)?;
+
+    writeln!(&mut file, "## Fitted Dirichlet Alpha")?;
+    writeln!(&mut file)?;
+    match summary.uncertainty {
+        _ if !summary.fit.converged => {
+            writeln!(
+                &mut file,
+                "The fit failed: {}.",
+                summary.fit.stop_reason.description()
+            )?;
+        }
+        Some(ref uncertainty) => {
+            writeln!(
+                &mut file,
+                "| | Alpha | Standard Error | 95% CI | Mean | Mean 95% CI |"
+            )?;
+            writeln!(&mut file, "| --- | ---: | ---: | ---: | ---: | ---: |")?;
+            for (index, label) in labels.iter().enumerate() {
+                writeln!(
+                    &mut file,
+                    "| {} | {:.3} | {:.3} | {} | {:.3} | {} |",
+                    label,
+                    alpha[index],
+                    uncertainty.standard_errors[index],
+                    interval(uncertainty.intervals[index]),
+                    alpha[index] / alpha_sum,
+                    interval(uncertainty.mean_intervals[index])
+                )?;
+            }
+            writeln!(
+                &mut file,
+                "| Sum | {:.3} | {:.3} | {} | | |",
+                alpha_sum,
+                uncertainty.sum_standard_error,
+                interval(uncertainty.sum_interval)
+            )?;
+            writeln!(&mut file)?;
+            writeln!(
+                &mut file,
+                "The standard errors are from the observed Fisher information of the fit."
+            )?;
+        }
+        None => {
+            writeln!(&mut file, "| | Alpha | Mean |")?;
+            writeln!(&mut file, "| --- | ---: | ---: |")?;
+            for (index, label) in labels.iter().enumerate() {
+                writeln!(
+                    &mut file,
+                    "| {} | {:.3} | {:.3} |",
+                    label,
+                    alpha[index],
+                    alpha[index] / alpha_sum
+                )?;
+            }
+            writeln!(&mut file, "| Sum | {:.3} | |", alpha_sum)?;
+            writeln!(&mut file)?;
+            writeln!(
+                &mut file,
+                "The standard errors couldn't be estimated because the fit isn't at a maximum \
+                 of the likelihood."
+            )?;
+        }
+    }
+    writeln!(&mut file)?;
+
+    writeln!(&mut file, "## Draw Rate Distribution")?;
+    writeln!(&mut file)?;
+    let mut histogram = [0u32; 10];
+    for &(_, result) in &openings {
+        let bin = (result.draw_proportion() * 10.) as usize;
+        histogram[cmp::min(bin, 9)] += 1;
+    }
+    let largest_bin = cmp::max(histogram.iter().cloned().max().unwrap_or(0), 1);
+    writeln!(&mut file, "| Draw Rate | Openings | |")?;
+    writeln!(&mut file, "| --- | ---: | --- |")?;
+    for (index, &count) in histogram.iter().enumerate() {
+        writeln!(
+            &mut file,
+            "| {}% to {}% | {} | {} |",
+            index * 10,
+            (index + 1) * 10,
+            count,
+            "\u{2588}".repeat((count * HISTOGRAM_WIDTH).div_ceil(largest_bin) as usize)
+        )?;
+    }
+    writeln!(&mut file)?;
+    // The mean draw rate and the imbalance of the openings need the fitted alpha
+    if summary.fit.converged {
+        writeln!(
+            &mut file,
+            "The fitted distribution has a mean draw rate of {:.1}%.",
+            100. * alpha[1] / alpha_sum
+        )?;
+        writeln!(&mut file)?;
+
+        let balance_rows = cmp::min(BALANCE_ROWS, openings.len().div_ceil(2));
+        let by_imbalance = sort_openings(opening_stats, SortBy::Imbalance, alpha);
+        writeln!(&mut file, "## Most Balanced Openings")?;
+        writeln!(&mut file)?;
+        print_markdown_openings(
+            &mut file,
+            by_imbalance.iter().rev().take(balance_rows),
+            key,
+            &labels,
+            alpha,
+        )?;
+        writeln!(&mut file, "## Least Balanced Openings")?;
+        writeln!(&mut file)?;
+        print_markdown_openings(
+            &mut file,
+            by_imbalance.iter().take(balance_rows),
+            key,
+            &labels,
+            alpha,
+        )?;
+        writeln!(
+            &mut file,
+            "The imbalance is the expected proportion of {} minus that of {} for the opening \
+             given the fitted distribution.",
+            labels[0],
+            labels[2]
+        )?;
+        writeln!(&mut file)?;
+    }
+
+    if let Some(ref bootstrap) = summary.bootstrap {
+        writeln!(&mut file, "## Bootstrap")?;
+        writeln!(&mut file)?;
+        writeln!(
+            &mut file,
+            "| Statistic | Estimate | Standard Error | {}% Interval |",
+            100. * bootstrap.confidence_level
+        )?;
+        writeln!(&mut file, "| --- | ---: | ---: | ---: |")?;
+        for statistic in &bootstrap.statistics {
+            writeln!(
+                &mut file,
+                "| {} | {:.3} | {} | {} |",
+                escape_markdown(&statistic.name),
+                statistic.estimate,
+                statistic
+                    .standard_error
+                    .map(|standard_error| format!("{:.3}", standard_error))
+                    .unwrap_or_default(),
+                statistic.interval.map(interval).unwrap_or_default()
+            )?;
+        }
+        writeln!(&mut file)?;
+        writeln!(
+            &mut file,
+            "From {} replicates, of which {} had an unbounded alpha, counted as infinite, and {} \
+             failed to converge.",
+            bootstrap.replicates, bootstrap.unbounded_replicates, bootstrap.failed_replicates
+        )?;
+        if bootstrap.too_many_failures() {
+            writeln!(
+                &mut file,
+                "The standard errors and intervals are left out as more than {}% of the \
+                 replicates failed.",
+                100. * MAX_FAILED_SHARE
+            )?;
+        }
+        writeln!(&mut file)?;
+    }
+
+    if let Some(ref goodness_of_fit) = summary.goodness_of_fit {
+        writeln!(&mut file, "## Goodness of Fit")?;
+        writeln!(&mut file)?;
+        match goodness_of_fit.degrees_of_freedom {
+            Some(degrees_of_freedom) => writeln!(
+                &mut file,
+                "The chi-square statistic comparing the number of openings with each result with \
+                 the number expected under the fit is {:.3} with {} degrees of freedom over {} \
+                 cells.",
+                goodness_of_fit.chi_square,
+                degrees_of_freedom,
+                goodness_of_fit.cells.len()
+            )?,
+            None => writeln!(
+                &mut file,
+                "The chi-square statistic comparing the number of openings with each result with \
+                 the number expected under the fit is {:.3} over {} cells. That is too few cells \
+                 for a chi-square p-value as their totals have {} constraints and 3 parameters \
+                 were fitted.",
+                goodness_of_fit.chi_square,
+                goodness_of_fit.cells.len(),
+                goodness_of_fit.constraints
+            )?,
+        }
+        writeln!(&mut file)?;
+        if let Some(p_value) = goodness_of_fit.p_value {
+            writeln!(&mut file, "- p-value: {:.4}", p_value)?;
+        }
+        if let Some(p_value) = goodness_of_fit.bootstrap_p_value {
+            writeln!(
+                &mut file,
+                "- Parametric bootstrap p-value: {:.4} from {} replicates",
+                p_value, goodness_of_fit.replicates
+            )?;
+            if goodness_of_fit.failed_replicates > 0 {
+                writeln!(
+                    &mut file,
+                    "- The fits of {} simulated books failed and they count as having a larger \
+                     statistic, so the bootstrap p-value is an upper bound",
+                    goodness_of_fit.failed_replicates
+                )?;
+            }
+        }
+        writeln!(&mut file)?;
+        writeln!(
+            &mut file,
+            "A small p-value means the openings aren't well described by a single Dirichlet \
+             distribution, e.g. because the book mixes drawish and sharp openings. See \
+             [goodness_of_fit.csv](goodness_of_fit.csv) for the observed and expected counts."
+        )?;
+        writeln!(&mut file)?;
+    }
+
+    if !summary.warnings.is_empty() {
+        writeln!(&mut file, "## Warnings")?;
+        writeln!(&mut file)?;
+        for warning in &summary.warnings {
+            writeln!(&mut file, "- {}", escape_markdown(warning))?;
+        }
+        writeln!(&mut file)?;
+    }
+
+    writeln!(&mut file, "## Plots")?;
+    writeln!(&mut file)?;
+    writeln!(&mut file, "![Scatter plot of the results](scatter_plot.svg)")?;
+    if summary.fit.converged {
+        writeln!(&mut file)?;
+        writeln!(
+            &mut file,
+            "![Contour plot of the fitted Dirichlet distribution](dirichlet_contour_plot.svg)"
+        )?;
+    }
+    Ok(())
+}
+
+fn interval(interval: (f64, f64)) -> String {
+    format!("{:.3} to {:.3}", interval.0, interval.1)
+}
+
+fn print_markdown_openings<'a, T, I>(
+    file: &mut T,
+    openings: I,
+    key: OpeningKey,
+    labels: &[&str; 3],
+    alpha: &[f64; 3],
+) -> Result<()>
+where
+    T: Write,
+    I: Iterator<Item = &'a (&'a String, &'a OpeningResult)>,
+{
+    writeln!(
+        file,
+        "| {} | Games | {} | {} | {} | Imbalance |",
+        if key.is_fen() { "FEN" } else { "Moves" },
+        labels[0],
+        labels[1],
+        labels[2]
+    )?;
+    writeln!(file, "| --- | ---: | ---: | ---: | ---: | ---: |")?;
+    for &(opening, result) in openings {
+        writeln!(
+            file,
+            "| `{}` | {} | {} | {} | {} | {:+.3} |",
+            opening.replace('|', "\\|"),
+            result.total_games(),
+            result.white_win_count,
+            result.draw_count,
+            result.black_win_count,
+            posterior_imbalance(&result.counts(), alpha)
+        )?;
+    }
+    writeln!(file)?;
+    Ok(())
+}
+
+fn print_row<T: Write>(file: &mut T, name: &str, value: &str) -> Result<()> {
+    writeln!(file, "  <tr><th>{}</th><td>{}</td></tr>", name, value)?;
+    Ok(())
+}
+
+fn cell(text: &str, value: f64) -> String {
+    format!("<td data-value=\"{}\">{}</td>", value, text)
+}
+
+// Removes the XML declaration so the SVG can be included in the HTML
+fn inline_svg(svg: &str) -> &str {
+    if svg.starts_with("<?xml") {
+        svg.find('\n').map_or("", |end| &svg[end + 1..])
+    } else {
+        svg
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Escapes the characters that Markdown would otherwise treat as formatting
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for char in text.chars() {
+        if "\\`*_[]<>|#".contains(char) {
+            escaped.push('\\');
+        }
+        escaped.push(char);
+    }
+    escaped
+}
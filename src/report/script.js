@@ -0,0 +1,54 @@
+    (function () {
+        var table = document.getElementById("openings");
+        var body = table.tBodies[0];
+        var rows = Array.prototype.slice.call(body.rows);
+        var headers = table.tHead.rows[0].cells;
+        var filter = document.getElementById("filter");
+        var minimum = document.getElementById("minimum");
+        var shown = document.getElementById("shown");
+
+        function applyFilter() {
+            var text = filter.value.toLowerCase();
+            var minimumTotal = parseInt(minimum.value, 10) || 0;
+            var count = 0;
+            rows.forEach(function (row) {
+                var visible = row.cells[0].textContent.toLowerCase().indexOf(text) >= 0
+                    && parseFloat(row.cells[1].getAttribute("data-value")) >= minimumTotal;
+                row.style.display = visible ? "" : "none";
+                if (visible) {
+                    count += 1;
+                }
+            });
+            shown.textContent = count;
+        }
+
+        function sortBy(index) {
+            var header = headers[index];
+            var ascending = !header.classList.contains("ascending");
+            Array.prototype.forEach.call(headers, function (other) {
+                other.classList.remove("ascending", "descending");
+            });
+            header.classList.add(ascending ? "ascending" : "descending");
+
+            rows.sort(function (left, right) {
+                var leftValue = left.cells[index].getAttribute("data-value");
+                var rightValue = right.cells[index].getAttribute("data-value");
+                var order = index === 0
+                    ? leftValue.localeCompare(rightValue)
+                    : parseFloat(leftValue) - parseFloat(rightValue);
+                return ascending ? order : -order;
+            });
+            rows.forEach(function (row) {
+                body.appendChild(row);
+            });
+        }
+
+        Array.prototype.forEach.call(headers, function (header, index) {
+            header.addEventListener("click", function () {
+                sortBy(index);
+            });
+        });
+        filter.addEventListener("input", applyFilter);
+        minimum.addEventListener("input", applyFilter);
+        applyFilter();
+    })();
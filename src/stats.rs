@@ -1,9 +1,9 @@
-use std;
-
 use special::Gamma;
 
-// The Dirichlet PDF with 3 categories.
-// Only p1 and p2 are required as p3 = 1. - p1 - p2.
+use {ErrorKind, Result};
+
+/// The Dirichlet PDF with 3 categories.
+/// Only p1 and p2 are required as p3 = 1. - p1 - p2.
 pub fn dirichlet_pdf(alpha: &[f64; 3], p1: f64, p2: f64) -> f64 {
     if p1 < 0. {
         panic!("p1 has a negative value")
@@ -22,43 +22,705 @@ pub fn dirichlet_pdf(alpha: &[f64; 3], p1: f64, p2: f64) -> f64 {
         * (p2.powf(alpha2 - 1.) / alpha2.gamma()) * (p3.powf(alpha3 - 1.) / alpha3.gamma())
 }
 
-// This function uses the fixed-point method for estimating the parameters of
-// The Dirichlet-multinomial/Polya distribution from
-// "Estimating a Dirichlet distibution" by Thomas P. Minka.
-// See https://tminka.github.io/papers/dirichlet/minka-dirichlet.pdf.
-// The input samples must be in the order [white_win_count, draw_count, black_win_count] and the
-// output is in the form [alpha_white, alpha_draw, alpha_black].
-pub fn fit_polya(samples: &[[u32; 3]]) -> [f64; 3] {
-    let mut alpha: [f64; 3] = [10., 10., 10.];
+/// The options of [`fit_polya`](fn.fit_polya.html).
+#[derive(Clone, Copy, Debug)]
+pub struct FitOptions {
+    /// The fit has converged when no alpha changes by more than this fraction of its value in
+    /// an iteration.
+    pub tolerance: f64,
+    /// The number of iterations after which the fit gives up.
+    pub max_iterations: u32,
+    /// The alpha the iteration starts from.
+    pub initial_alpha: InitialAlpha,
+    pub method: FitMethod,
+}
 
-    loop {
-        let old_alpha = alpha;
-        let alpha_sum = alpha.iter().sum::<f64>();
+impl Default for FitOptions {
+    fn default() -> FitOptions {
+        FitOptions {
+            tolerance: 1e-10,
+            max_iterations: 100_000,
+            initial_alpha: InitialAlpha::Value([10., 10., 10.]),
+            method: FitMethod::FixedPoint,
+        }
+    }
+}
+
+/// The alpha [`fit_polya`](fn.fit_polya.html) starts from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InitialAlpha {
+    Value([f64; 3]),
+    /// The estimate of [`moments_alpha`](fn.moments_alpha.html), or 10 for every result if the
+    /// samples don't give one.
+    Moments,
+}
+
+/// How [`fit_polya`](fn.fit_polya.html) maximises the likelihood. Both methods are from
+/// "Estimating a Dirichlet distribution" by Thomas P. Minka.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FitMethod {
+    /// The fixed-point iteration, which always increases the likelihood but takes many
+    /// iterations when alpha is large e.g. for very drawish books.
+    FixedPoint,
+    /// Newton's method, which converges in a few iterations near the maximum. Steps are
+    /// shortened so alpha stays positive and the likelihood doesn't decrease, and a fixed-point
+    /// step is taken when a Newton step can't increase the likelihood.
+    Newton,
+}
+
+/// Why [`fit_polya`](fn.fit_polya.html) stopped.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StopReason {
+    /// The change of alpha fell below the tolerance.
+    Converged,
+    /// The maximum number of iterations was reached first.
+    MaxIterations,
+    /// An alpha stopped being positive and finite. This happens when a result never or always
+    /// occurs e.g. when every game is a draw.
+    Diverged,
+    /// The likelihood keeps increasing as alpha grows so there is no fit. This happens when the
+    /// results of the openings vary no more than expected from their numbers of games alone,
+    /// e.g. when there's only one opening, so there is no dispersion for a Dirichlet
+    /// distribution to describe beyond that of the multinomial distribution.
+    Unbounded,
+    /// There are no games to fit.
+    NoGames,
+}
+
+impl StopReason {
+    /// A short description of the reason.
+    pub fn description(&self) -> &'static str {
+        match *self {
+            StopReason::Converged => "converged",
+            StopReason::MaxIterations => "reached the maximum number of iterations",
+            StopReason::Diverged => "alpha diverged",
+            StopReason::Unbounded => {
+                "alpha grows without bound as the openings vary no more than their games do"
+            }
+            StopReason::NoGames => "no games to fit",
+        }
+    }
+}
+
+/// The result of fitting a Dirichlet-multinomial distribution with
+/// [`fit_polya`](fn.fit_polya.html).
+#[derive(Clone, Copy, Debug)]
+pub struct PolyaFit {
+    /// The alpha of the last iteration, in the form [alpha_white, alpha_draw, alpha_black].
+    pub alpha: [f64; 3],
+    pub iterations: u32,
+    pub converged: bool,
+    /// The log-likelihood of the samples with `alpha`.
+    pub log_likelihood: f64,
+    pub stop_reason: StopReason,
+}
+
+impl PolyaFit {
+    /// A converged fit with `alpha`, for testing the statistics that use a fit.
+    #[cfg(test)]
+    pub fn with_alpha(alpha: [f64; 3]) -> PolyaFit {
+        PolyaFit {
+            alpha,
+            iterations: 0,
+            converged: true,
+            log_likelihood: 0.,
+            stop_reason: StopReason::Converged,
+        }
+    }
+
+    /// Returns the fit if it converged and an error otherwise.
+    pub fn check(self) -> Result<PolyaFit> {
+        if self.converged {
+            Ok(self)
+        } else if self.stop_reason == StopReason::Unbounded {
+            Err(From::from(ErrorKind::NotConverged(format!(
+                "The Dirichlet distribution can't be fitted: {}",
+                self.stop_reason.description()
+            ))))
+        } else {
+            Err(From::from(ErrorKind::NotConverged(format!(
+                "The Dirichlet fit stopped after {} iterations ({}) with alpha \
+                 ({:.3}, {:.3}, {:.3})",
+                self.iterations,
+                self.stop_reason.description(),
+                self.alpha[0],
+                self.alpha[1],
+                self.alpha[2]
+            ))))
+        }
+    }
+}
+
+/// This function uses the fixed-point or Newton method for estimating the parameters of
+/// The Dirichlet-multinomial/Polya distribution from
+/// "Estimating a Dirichlet distibution" by Thomas P. Minka.
+/// See https://tminka.github.io/papers/dirichlet/minka-dirichlet.pdf.
+/// The input samples must be in the order [white_win_count, draw_count, black_win_count] and the
+/// fitted alpha is in the form [alpha_white, alpha_draw, alpha_black].
+///
+/// The iteration stops when it converges, diverges or reaches `options.max_iterations`, so the
+/// fit should be checked before it's used e.g. with
+/// [`PolyaFit::check`](struct.PolyaFit.html#method.check). It isn't started when the likelihood
+/// has no maximum because alpha would grow without bound.
+pub fn fit_polya(samples: &[[u32; 3]], options: &FitOptions) -> PolyaFit {
+    let mut alpha = match options.initial_alpha {
+        InitialAlpha::Value(alpha) => alpha,
+        InitialAlpha::Moments => moments_alpha(samples).unwrap_or([10., 10., 10.]),
+    };
+    let mut iterations = 0;
+
+    let stop_reason = if samples.iter().all(|s| s.iter().all(|&count| count == 0)) {
+        StopReason::NoGames
+    } else if is_unbounded(samples) {
+        StopReason::Unbounded
+    } else {
+        loop {
+            if iterations == options.max_iterations {
+                break StopReason::MaxIterations;
+            }
+            iterations += 1;
+
+            let old_alpha = alpha;
+            alpha = match options.method {
+                FitMethod::FixedPoint => fixed_point_step(samples, &alpha),
+                FitMethod::Newton => newton_step(samples, &alpha),
+            };
+
+            if alpha.iter().any(|&value| value <= 0. || !value.is_finite()) {
+                break StopReason::Diverged;
+            }
+
+            let change = old_alpha
+                .iter()
+                .zip(alpha.iter())
+                .map(|(&left, &right)| ((left - right) / right).abs())
+                .fold(0., f64::max);
+
+            if change < options.tolerance {
+                break StopReason::Converged;
+            }
+        }
+    };
+
+    PolyaFit {
+        alpha,
+        iterations,
+        converged: stop_reason == StopReason::Converged,
+        log_likelihood: log_likelihood(samples, &alpha),
+        stop_reason,
+    }
+}
+
+// Whether the likelihood increases without bound as alpha grows. With the mean of the Dirichlet
+// distribution at the proportions of all the games the log-likelihood is that of the
+// multinomial distribution plus D / (2 * sum(alpha)) for large alpha, where D compares the
+// variation of the openings with that of multinomial samples. Alpha goes to infinity when D
+// isn't positive. A result that never occurs makes its alpha go to zero instead.
+fn is_unbounded(samples: &[[u32; 3]]) -> bool {
+    let mut totals = [0.; 3];
+    for sample in samples {
+        for index in 0..3 {
+            totals[index] += f64::from(sample[index]);
+        }
+    }
+    if totals.contains(&0.) {
+        return false;
+    }
+    let game_count = totals.iter().sum::<f64>();
+
+    let d = samples
+        .iter()
+        .map(|sample| {
+            let games = f64::from(sample.iter().sum::<u32>());
+            let pairs = (0..3)
+                .map(|index| {
+                    let count = f64::from(sample[index]);
+                    count * (count - 1.) * game_count / totals[index]
+                })
+                .sum::<f64>();
+            pairs - games * (games - 1.)
+        })
+        .sum::<f64>();
+    d <= 0. || d.is_nan()
+}
+
+// An iteration of the fixed-point method
+fn fixed_point_step(samples: &[[u32; 3]], alpha: &[f64; 3]) -> [f64; 3] {
+    let alpha_sum = alpha.iter().sum::<f64>();
+
+    let denominator: f64 = samples
+        .iter()
+        .map(|s| (f64::from(s.iter().sum::<u32>()) + alpha_sum).digamma() - alpha_sum.digamma())
+        .sum::<f64>();
 
-        let denominator: f64 = samples
+    let mut new_alpha = [0.; 3];
+    for index in 0..3 {
+        let term1 = samples
             .iter()
-            .map(|s| (f64::from(s.iter().sum::<u32>()) + alpha_sum).digamma() - alpha_sum.digamma())
+            .map(|s| (f64::from(s[index]) + alpha[index]).digamma() - alpha[index].digamma())
             .sum::<f64>();
+        let numerator = alpha[index] * term1;
+        new_alpha[index] = numerator / denominator;
+    }
+    new_alpha
+}
 
+// An iteration of Newton's method. The Hessian of the log-likelihood is a diagonal matrix plus
+// a constant so the step is found without inverting it, as in section 3 of Minka's paper.
+fn newton_step(samples: &[[u32; 3]], alpha: &[f64; 3]) -> [f64; 3] {
+    let alpha_sum = alpha.iter().sum::<f64>();
+    let mut gradient = [0.; 3];
+    let mut diagonal = [0.; 3];
+    let mut constant = 0.;
+    for sample in samples {
+        let total = sample.iter().sum::<u32>();
+        let common = alpha_sum.digamma() - (f64::from(total) + alpha_sum).digamma();
+        constant += trigamma_difference(alpha_sum, total);
         for index in 0..3 {
-            let term1 = samples
-                .iter()
-                .map(|s| (f64::from(s[index]) + alpha[index]).digamma() - alpha[index].digamma())
-                .sum::<f64>();
-            let numerator = alpha[index] * term1;
-            alpha[index] = numerator / denominator;
+            gradient[index] += common + (f64::from(sample[index]) + alpha[index]).digamma()
+                - alpha[index].digamma();
+            diagonal[index] -= trigamma_difference(alpha[index], sample[index]);
+        }
+    }
+    // A result that never occurs has no curvature and its alpha goes to zero
+    if diagonal.contains(&0.) {
+        return fixed_point_step(samples, alpha);
+    }
+
+    let b = (0..3).map(|index| gradient[index] / diagonal[index]).sum::<f64>()
+        / (1. / constant + diagonal.iter().map(|value| 1. / value).sum::<f64>());
+    let mut direction = [0.; 3];
+    for index in 0..3 {
+        direction[index] = -(gradient[index] - b) / diagonal[index];
+    }
+    let slope = (0..3)
+        .map(|index| gradient[index] * direction[index])
+        .sum::<f64>();
+    if slope <= 0. || slope.is_nan() {
+        return fixed_point_step(samples, alpha);
+    }
+
+    let old_log_likelihood = log_likelihood(samples, alpha);
+    let mut step = 1.;
+    for _ in 0..MAX_STEP_HALVINGS {
+        let new_alpha = [
+            alpha[0] + step * direction[0],
+            alpha[1] + step * direction[1],
+            alpha[2] + step * direction[2],
+        ];
+        if new_alpha.iter().all(|&value| value > 0.)
+            && log_likelihood(samples, &new_alpha) >= old_log_likelihood
+        {
+            return new_alpha;
+        }
+        step /= 2.;
+    }
+    fixed_point_step(samples, alpha)
+}
+
+// The number of times a Newton step is halved before a fixed-point step is taken instead
+const MAX_STEP_HALVINGS: u32 = 40;
+
+/// Estimates alpha from the samples by the method of moments, e.g. to start
+/// [`fit_polya`](fn.fit_polya.html) from.
+///
+/// The mean of the Dirichlet distribution is the proportion of each result in all the games and
+/// its concentration comes from how much the proportions of the openings vary around it,
+/// allowing for the number of games of each opening. Returns `None` if a result never occurs
+/// or the proportions vary too much or too little for a positive and finite alpha.
+pub fn moments_alpha(samples: &[[u32; 3]]) -> Option<[f64; 3]> {
+    let samples: Vec<&[u32; 3]> = samples
+        .iter()
+        .filter(|sample| sample.iter().any(|&count| count > 0))
+        .collect();
+    if samples.is_empty() {
+        return None;
+    }
+    let opening_count = samples.len() as f64;
+
+    let mut totals = [0.; 3];
+    for sample in &samples {
+        for index in 0..3 {
+            totals[index] += f64::from(sample[index]);
+        }
+    }
+    let game_count = totals.iter().sum::<f64>();
+    let mean = [
+        totals[0] / game_count,
+        totals[1] / game_count,
+        totals[2] / game_count,
+    ];
+    if mean.contains(&0.) {
+        return None;
+    }
+
+    // The proportions of an opening with n games have a variance of
+    // mean * (1 - mean) * (1 + sum / n) / (sum + 1) where sum is the sum of alpha
+    let mut variance = 0.;
+    let mut inverse_games = 0.;
+    for sample in &samples {
+        let total = f64::from(sample.iter().sum::<u32>());
+        for index in 0..3 {
+            variance += (f64::from(sample[index]) / total - mean[index]).powi(2);
         }
+        inverse_games += 1. / total;
+    }
+    // The ratio of the variance to mean * (1 - mean) is 1 / n when every opening has the same
+    // proportions and goes up to 1 as they vary more
+    let binomial_variance = mean.iter().map(|&value| value * (1. - value)).sum::<f64>();
+    let ratio = variance / opening_count / binomial_variance;
+    let ratio_without_variation = inverse_games / opening_count;
+    if !(ratio > ratio_without_variation && ratio < 1.) {
+        return None;
+    }
+
+    let alpha_sum = (1. - ratio) / (ratio - ratio_without_variation);
+    Some([
+        alpha_sum * mean[0],
+        alpha_sum * mean[1],
+        alpha_sum * mean[2],
+    ])
+}
+
+/// The log-likelihood of the samples under the Dirichlet-multinomial distribution with
+/// parameters `alpha`. The samples are in the order of `fit_polya`.
+pub fn log_likelihood(samples: &[[u32; 3]], alpha: &[f64; 3]) -> f64 {
+    samples
+        .iter()
+        .map(|sample| polya_log_probability(sample, alpha))
+        .sum()
+}
+
+/// The log of the probability of the counts of results of an opening under the
+/// Dirichlet-multinomial distribution with parameters `alpha`, given its number of games. The
+/// counts are in the order of `fit_polya`.
+pub fn polya_log_probability(counts: &[u32; 3], alpha: &[f64; 3]) -> f64 {
+    let alpha_sum = alpha.iter().sum::<f64>();
+    let total = f64::from(counts.iter().sum::<u32>());
+    let categories = counts
+        .iter()
+        .zip(alpha.iter())
+        .map(|(&count, &alpha)| {
+            let count = f64::from(count);
+            Gamma::ln_gamma(count + alpha).0 - Gamma::ln_gamma(alpha).0
+                - Gamma::ln_gamma(count + 1.).0
+        })
+        .sum::<f64>();
+    Gamma::ln_gamma(total + 1.).0 + Gamma::ln_gamma(alpha_sum).0
+        - Gamma::ln_gamma(total + alpha_sum).0 + categories
+}
 
-        let dist2 = old_alpha
+/// The covariance matrix of the parameters `alpha` fitted to the samples with `fit_polya`,
+/// estimated by the inverse of the observed Fisher information of the Dirichlet-multinomial
+/// likelihood. Returns `None` if the information matrix can't be inverted, e.g. when the fit
+/// didn't reach a maximum.
+pub fn alpha_covariance(samples: &[[u32; 3]], alpha: &[f64; 3]) -> Option<[[f64; 3]; 3]> {
+    let alpha_sum = alpha.iter().sum::<f64>();
+    let common = -samples
+        .iter()
+        .map(|sample| trigamma_difference(alpha_sum, sample.iter().sum()))
+        .sum::<f64>();
+    let mut information = [[common; 3]; 3];
+    for index in 0..3 {
+        information[index][index] += samples
             .iter()
-            .zip(alpha.iter())
-            .map(|(&left, &right)| (left - right) * (left - right))
+            .map(|sample| trigamma_difference(alpha[index], sample[index]))
             .sum::<f64>();
+    }
+
+    let covariance = invert(&information)?;
+    if covariance.iter().enumerate().all(|(index, row)| row[index] > 0.) {
+        Some(covariance)
+    } else {
+        None
+    }
+}
+
+/// The uncertainty of the parameters `alpha` fitted with [`fit_polya`](fn.fit_polya.html), of
+/// their sum (the concentration) and of the mean proportions of the results they imply.
+#[derive(Clone, Copy, Debug)]
+pub struct AlphaUncertainty {
+    /// The covariance matrix of alpha from [`alpha_covariance`](fn.alpha_covariance.html).
+    pub covariance: [[f64; 3]; 3],
+    pub standard_errors: [f64; 3],
+    /// Confidence intervals for alpha that are symmetric on the log scale so they stay
+    /// positive.
+    pub intervals: [(f64, f64); 3],
+    pub sum_standard_error: f64,
+    pub sum_interval: (f64, f64),
+    /// The standard errors of the means found with the delta method.
+    pub mean_standard_errors: [f64; 3],
+    /// Normal approximation intervals for the means, clamped to [0, 1].
+    pub mean_intervals: [(f64, f64); 3],
+}
+
+/// The standard errors and confidence intervals of `alpha` fitted to the samples, with `z` the
+/// quantile of the standard normal distribution for the confidence level e.g. `Z_95`. Returns
+/// `None` when [`alpha_covariance`](fn.alpha_covariance.html) does.
+pub fn alpha_uncertainty(
+    samples: &[[u32; 3]],
+    alpha: &[f64; 3],
+    z: f64,
+) -> Option<AlphaUncertainty> {
+    let covariance = alpha_covariance(samples, alpha)?;
+    let alpha_sum = alpha.iter().sum::<f64>();
+    let log_interval = |value: f64, standard_error: f64| {
+        let factor = (z * standard_error / value).exp();
+        (value / factor, value * factor)
+    };
+
+    let mut uncertainty = AlphaUncertainty {
+        covariance,
+        standard_errors: [0.; 3],
+        intervals: [(0., 0.); 3],
+        sum_standard_error: covariance
+            .iter()
+            .map(|row| row.iter().sum::<f64>())
+            .sum::<f64>()
+            .sqrt(),
+        sum_interval: (0., 0.),
+        mean_standard_errors: [0.; 3],
+        mean_intervals: [(0., 0.); 3],
+    };
+    uncertainty.sum_interval = log_interval(alpha_sum, uncertainty.sum_standard_error);
+    for index in 0..3 {
+        let standard_error = covariance[index][index].sqrt();
+        uncertainty.standard_errors[index] = standard_error;
+        uncertainty.intervals[index] = log_interval(alpha[index], standard_error);
+
+        // The derivatives of alpha[index] / alpha_sum with respect to each alpha
+        let mut gradient = [-alpha[index] / (alpha_sum * alpha_sum); 3];
+        gradient[index] += 1. / alpha_sum;
+        let variance = (0..3)
+            .map(|row| {
+                (0..3)
+                    .map(|column| gradient[row] * covariance[row][column] * gradient[column])
+                    .sum::<f64>()
+            })
+            .sum::<f64>();
+        let mean = alpha[index] / alpha_sum;
+        let standard_error = variance.max(0.).sqrt();
+        uncertainty.mean_standard_errors[index] = standard_error;
+        uncertainty.mean_intervals[index] = (
+            (mean - z * standard_error).max(0.),
+            (mean + z * standard_error).min(1.),
+        );
+    }
+    Some(uncertainty)
+}
+
+// trigamma(a) - trigamma(a + n), which for integer counts is the sum of 1 / (a + k)^2 for k < n
+fn trigamma_difference(a: f64, n: u32) -> f64 {
+    (0..n).map(|k| 1. / (a + f64::from(k)).powi(2)).sum()
+}
+
+// The inverse of a 3x3 matrix from its adjugate
+fn invert(matrix: &[[f64; 3]; 3]) -> Option<[[f64; 3]; 3]> {
+    let mut adjugate = [[0.; 3]; 3];
+    for (row, adjugate_row) in adjugate.iter_mut().enumerate() {
+        for (column, value) in adjugate_row.iter_mut().enumerate() {
+            let (r1, r2) = ((column + 1) % 3, (column + 2) % 3);
+            let (c1, c2) = ((row + 1) % 3, (row + 2) % 3);
+            *value = matrix[r1][c1] * matrix[r2][c2] - matrix[r1][c2] * matrix[r2][c1];
+        }
+    }
+    let determinant = (0..3)
+        .map(|index| matrix[0][index] * adjugate[index][0])
+        .sum::<f64>();
+    if determinant == 0. || !determinant.is_finite() {
+        return None;
+    }
+    for row in adjugate.iter_mut() {
+        for value in row.iter_mut() {
+            *value /= determinant;
+        }
+    }
+    Some(adjugate)
+}
+
+/// The difference between the posterior mean proportions of white wins and black wins of an
+/// opening with the counts [white_win_count, draw_count, black_win_count] given a Dirichlet
+/// prior with parameters `alpha`.
+pub fn posterior_imbalance(counts: &[u32; 3], alpha: &[f64; 3]) -> f64 {
+    let total = f64::from(counts.iter().sum::<u32>()) + alpha.iter().sum::<f64>();
+    ((f64::from(counts[0]) + alpha[0]) - (f64::from(counts[2]) + alpha[2])) / total
+}
+
+/// The quantile of the standard normal distribution for a two-sided 95% confidence interval.
+pub const Z_95: f64 = 1.959963984540054;
+
+/// The Wilson score interval for the proportion of `successes` in `trials`, with `z` the
+/// quantile of the standard normal distribution for the confidence level e.g. `Z_95`.
+pub fn wilson_interval(successes: u32, trials: u32, z: f64) -> (f64, f64) {
+    if trials == 0 {
+        return (0., 1.);
+    }
+    let n = f64::from(trials);
+    let p = f64::from(successes) / n;
+    let z2 = z * z;
+    let centre = (p + z2 / (2. * n)) / (1. + z2 / n);
+    let half_width = z / (1. + z2 / n) * (p * (1. - p) / n + z2 / (4. * n * n)).sqrt();
+    (centre - half_width, centre + half_width)
+}
+
+/// The normal approximation interval for the mean score of the games with the counts
+/// [white_win_count, draw_count, black_win_count], where a win scores 1 and a draw 1/2.
+/// The interval is clamped to [0, 1].
+pub fn score_interval(counts: &[u32; 3], z: f64) -> (f64, f64) {
+    let n = f64::from(counts.iter().sum::<u32>());
+    if n == 0. {
+        return (0., 1.);
+    }
+    let score = (f64::from(counts[0]) + f64::from(counts[1]) / 2.) / n;
+    let variance = (f64::from(counts[0]) + f64::from(counts[1]) / 4.) / n - score * score;
+    let half_width = z * (variance.max(0.) / n).sqrt();
+    ((score - half_width).max(0.), (score + half_width).min(1.))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Draws the results of the games of each opening from a Polya urn, which gives samples of
+    // the Dirichlet-multinomial distribution with parameters `alpha`
+    fn polya_samples(alpha: &[f64; 3], openings: usize, games: u32, seed: u64) -> Vec<[u32; 3]> {
+        let mut state = seed;
+        let mut uniform = || {
+            // xorshift64*
+            state ^= state >> 12;
+            state ^= state << 25;
+            state ^= state >> 27;
+            (state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let alpha_sum = alpha.iter().sum::<f64>();
+        (0..openings)
+            .map(|_| {
+                let mut counts = [0; 3];
+                for game in 0..games {
+                    let mut draw = uniform() * (alpha_sum + f64::from(game));
+                    let mut result = 0;
+                    while result < 2 && draw >= alpha[result] + f64::from(counts[result]) {
+                        draw -= alpha[result] + f64::from(counts[result]);
+                        result += 1;
+                    }
+                    counts[result] += 1;
+                }
+                counts
+            })
+            .collect()
+    }
 
-        if dist2 < std::f64::EPSILON {
-            break;
+    fn fit(samples: &[[u32; 3]], method: FitMethod, initial_alpha: InitialAlpha) -> PolyaFit {
+        let options = FitOptions {
+            method,
+            initial_alpha,
+            ..FitOptions::default()
+        };
+        fit_polya(samples, &options)
+    }
+
+    fn assert_agree(left: &[f64; 3], right: &[f64; 3]) {
+        for index in 0..3 {
+            let difference = (left[index] - right[index]).abs() / left[index];
+            assert!(difference < 1e-6, "{:?} and {:?} differ", left, right);
         }
     }
 
-    alpha
+    #[test]
+    fn methods_agree() {
+        let true_alphas = [[1.4, 0.7, 0.4], [2., 2., 2.], [0.5, 3., 0.3]];
+        for (seed, true_alpha) in true_alphas.iter().enumerate() {
+            let samples = polya_samples(true_alpha, 200, 10, seed as u64 + 1);
+            let fixed_point = fit(&samples, FitMethod::FixedPoint, InitialAlpha::Value([10.; 3]));
+            let newton = fit(&samples, FitMethod::Newton, InitialAlpha::Value([10.; 3]));
+            assert!(fixed_point.converged);
+            assert!(newton.converged);
+            assert_agree(&fixed_point.alpha, &newton.alpha);
+            assert!(newton.iterations < fixed_point.iterations);
+        }
+    }
+
+    #[test]
+    fn methods_agree_when_alpha_is_large() {
+        let samples = polya_samples(&[4., 40., 3.], 300, 40, 7);
+        let fixed_point = fit(&samples, FitMethod::FixedPoint, InitialAlpha::Value([10.; 3]));
+        let newton = fit(&samples, FitMethod::Newton, InitialAlpha::Moments);
+        assert!(fixed_point.converged);
+        assert!(newton.converged);
+        assert_agree(&fixed_point.alpha, &newton.alpha);
+        assert!(newton.iterations * 10 < fixed_point.iterations);
+    }
+
+    #[test]
+    fn starting_alpha_does_not_change_fit() {
+        let samples = polya_samples(&[1.4, 0.7, 0.4], 100, 10, 3);
+        let from_ten = fit(&samples, FitMethod::Newton, InitialAlpha::Value([10.; 3]));
+        let from_small = fit(&samples, FitMethod::Newton, InitialAlpha::Value([0.01; 3]));
+        let from_moments = fit(&samples, FitMethod::FixedPoint, InitialAlpha::Moments);
+        assert_agree(&from_ten.alpha, &from_small.alpha);
+        assert_agree(&from_ten.alpha, &from_moments.alpha);
+    }
+
+    #[test]
+    fn moments_alpha_is_near_fit() {
+        let samples = polya_samples(&[1.4, 0.7, 0.4], 500, 10, 5);
+        let moments = moments_alpha(&samples).unwrap();
+        let fitted = fit(&samples, FitMethod::Newton, InitialAlpha::Moments).alpha;
+        for index in 0..3 {
+            assert!((moments[index] / fitted[index] - 1.).abs() < 0.25);
+        }
+    }
+
+    #[test]
+    fn moments_alpha_needs_every_result() {
+        assert_eq!(moments_alpha(&[[0, 10, 0], [0, 8, 0]]), None);
+        assert_eq!(moments_alpha(&[]), None);
+    }
+
+    #[test]
+    fn fit_stops_when_every_game_is_a_draw() {
+        let samples = [[0, 10, 0], [0, 10, 0]];
+        for &method in &[FitMethod::FixedPoint, FitMethod::Newton] {
+            let fit = fit(&samples, method, InitialAlpha::Value([10.; 3]));
+            assert!(!fit.converged);
+            assert!(fit.check().is_err());
+        }
+    }
+
+    #[test]
+    fn fit_stops_when_openings_vary_no_more_than_games() {
+        let single_opening = [[12, 30, 8]];
+        let same_proportions = [[5, 3, 2], [5, 3, 2], [10, 6, 4], [5, 3, 2]];
+        for samples in &[&single_opening[..], &same_proportions[..]] {
+            for &method in &[FitMethod::FixedPoint, FitMethod::Newton] {
+                let fit = fit(samples, method, InitialAlpha::Value([10.; 3]));
+                assert_eq!(fit.stop_reason, StopReason::Unbounded);
+                assert!(fit.check().is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn tolerance_is_relative() {
+        let samples = polya_samples(&[0.02, 0.05, 0.01], 100, 20, 13);
+        let fixed_point = fit(&samples, FitMethod::FixedPoint, InitialAlpha::Value([10.; 3]));
+        let newton = fit(&samples, FitMethod::Newton, InitialAlpha::Value([10.; 3]));
+        assert!(fixed_point.converged);
+        assert_agree(&fixed_point.alpha, &newton.alpha);
+
+        // An absolute tolerance this small is below the rounding error of an alpha this large
+        let samples = polya_samples(&[200., 500., 100.], 400, 100, 13);
+        let from_moments = fit(&samples, FitMethod::Newton, InitialAlpha::Moments);
+        let newton = fit(&samples, FitMethod::Newton, InitialAlpha::Value([10.; 3]));
+        assert!(from_moments.converged);
+        assert!(newton.converged);
+        assert_agree(&from_moments.alpha, &newton.alpha);
+    }
+
+    #[test]
+    fn fit_stops_without_games() {
+        let fit = fit(&[[0, 0, 0]], FitMethod::Newton, InitialAlpha::Moments);
+        assert_eq!(fit.stop_reason, StopReason::NoGames);
+        assert_eq!(fit.iterations, 0);
+    }
 }